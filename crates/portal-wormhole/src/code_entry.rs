@@ -0,0 +1,200 @@
+//! Helps with typing in codes, similar to the completion of the Python client:
+//! First the nameplate number, then the words from the PGP wordlist.
+
+use crate::send::DEFAULT_WORD_COUNT;
+use magic_wormhole::Wordlist;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+fn wordlist() -> &'static Wordlist {
+    static WORDLIST: OnceLock<Wordlist> = OnceLock::new();
+    WORDLIST.get_or_init(|| Wordlist::default_wordlist(DEFAULT_WORD_COUNT))
+}
+
+fn is_separator(c: char) -> bool {
+    c == '-' || c.is_whitespace()
+}
+
+/// Separates the parts of the code by single dashes and lowercases words from the wordlist,
+/// e.g. `" 4 Hurricane  equipment"` becomes `"4-hurricane-equipment"`.
+/// Other parts are kept as typed, as the password of a custom code is case-sensitive.
+pub fn normalize_code(input: &str) -> String {
+    input
+        .split(is_separator)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            if is_word(part) {
+                part.to_lowercase()
+            } else {
+                part.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The codes that the last, partially typed word can be completed to.
+pub fn code_completions(input: &str) -> Vec<String> {
+    let code = normalize_code(input);
+    let ends_with_separator = input.ends_with(is_separator);
+    match code.split_once('-') {
+        Some((nameplate, _)) if !ends_with_separator && is_nameplate(nameplate) => {
+            wordlist().get_completions(&code)
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The code after pressing tab: Unique completions complete the word (and start the next one),
+/// ambiguous completions are completed as far as they agree.
+pub fn complete_code(input: &str) -> Option<String> {
+    let completions = code_completions(input);
+    let completed = match completions.as_slice() {
+        [] => return None,
+        [completion] => {
+            let word_count = completion.split('-').count() - 1;
+            if word_count < DEFAULT_WORD_COUNT {
+                format!("{completion}-")
+            } else {
+                completion.clone()
+            }
+        }
+        [first, rest @ ..] => rest.iter().fold(first.clone(), |prefix, completion| {
+            common_prefix(&prefix, completion).to_owned()
+        }),
+    };
+    (completed != normalize_code(input)).then_some(completed)
+}
+
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let length = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(a.len().min(b.len()), |((index, _), _)| index);
+    &a[..length]
+}
+
+/// Byte ranges of the parts of the input that are neither a nameplate nor a word from the wordlist.
+/// The last word is only reported once it can't be completed anymore, so that it isn't flagged while typing.
+pub fn unknown_words(input: &str) -> Vec<Range<usize>> {
+    let ends_with_separator = input.ends_with(is_separator);
+    let parts = parts(input);
+    let last = parts.len().saturating_sub(1);
+    parts
+        .into_iter()
+        .enumerate()
+        .filter(|(index, range)| {
+            let part = &input[range.clone()];
+            if *index == 0 {
+                !is_nameplate(part)
+            } else if *index == last && !ends_with_separator {
+                !is_word_prefix(part)
+            } else {
+                !is_word(part)
+            }
+        })
+        .map(|(_, range)| range)
+        .collect()
+}
+
+fn parts(input: &str) -> Vec<Range<usize>> {
+    let mut parts = Vec::new();
+    let mut start = None;
+    for (index, c) in input.char_indices() {
+        match (is_separator(c), start) {
+            (true, Some(part_start)) => {
+                parts.push(part_start..index);
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    if let Some(part_start) = start {
+        parts.push(part_start..input.len());
+    }
+    parts
+}
+
+fn is_nameplate(part: &str) -> bool {
+    !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Words alternate between the two halves of the wordlist, but we accept words from either half
+/// as codes don't have to be generated from the wordlist.
+fn words_starting_with(partial: &str) -> impl Iterator<Item = String> {
+    let partial = partial.to_lowercase();
+    let odd_words = wordlist().get_completions(&partial);
+    let even_words = wordlist().get_completions(&format!("-{partial}"));
+    odd_words.into_iter().chain(even_words)
+}
+
+fn is_word(part: &str) -> bool {
+    words_starting_with(part).any(|word| word.eq_ignore_ascii_case(part))
+}
+
+fn is_word_prefix(part: &str) -> bool {
+    words_starting_with(part).next().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_whitespace_and_case() {
+        assert_eq!(
+            normalize_code(" 4 Hurricane  equipment\t"),
+            "4-hurricane-equipment"
+        );
+        assert_eq!(normalize_code("4--hurricane-"), "4-hurricane");
+    }
+
+    #[test]
+    fn keeps_case_of_custom_passwords() {
+        assert_eq!(
+            normalize_code("4 Hurricane MySecret"),
+            "4-hurricane-MySecret"
+        );
+    }
+
+    #[test]
+    fn completes_unique_word_and_starts_next_one() {
+        assert_eq!(complete_code("4-hurric").as_deref(), Some("4-hurricane-"));
+        assert_eq!(
+            complete_code("4 Hurricane hock").as_deref(),
+            Some("4-hurricane-hockey-")
+        );
+        assert_eq!(
+            complete_code("4-hurricane-hockey-hurricane-hock").as_deref(),
+            Some("4-hurricane-hockey-hurricane-hockey")
+        );
+    }
+
+    #[test]
+    fn completes_ambiguous_words_as_far_as_they_agree() {
+        let completions = code_completions("4-st");
+        assert!(completions.len() > 1);
+        assert!(completions.iter().all(|code| code.starts_with("4-st")));
+        assert_eq!(complete_code("4-st"), None);
+    }
+
+    #[test]
+    fn does_not_complete_without_nameplate() {
+        assert_eq!(code_completions("hurric"), Vec::<String>::new());
+        assert_eq!(code_completions("x-hurric"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_unknown_words() {
+        let input = "4-hurricane-hockee";
+        let unknown: Vec<_> = unknown_words(input)
+            .into_iter()
+            .map(|range| &input[range])
+            .collect();
+        assert_eq!(unknown, ["hockee"]);
+        assert_eq!(unknown_words("4-hurri"), Vec::<Range<usize>>::new());
+        assert_eq!(unknown_words("four Hurricane "), vec![0..4]);
+    }
+}
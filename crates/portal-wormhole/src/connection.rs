@@ -0,0 +1,93 @@
+use crate::protocol::AppVersion;
+use crate::transit::{default_relay_server, relay_hints};
+use crate::PortalError;
+use magic_wormhole::rendezvous::DEFAULT_RENDEZVOUS_SERVER;
+use magic_wormhole::transfer;
+use magic_wormhole::transit::RelayHint;
+use magic_wormhole::{AppConfig, AppID};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The servers used to find and connect to the peer.
+///
+/// The defaults point to the public servers operated by the Magic Wormhole project.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionSettings {
+    /// The rendezvous server (also known as mailbox server) used to exchange the code.
+    pub rendezvous_url: Url,
+    /// Both peers need to use the same app id to be able to talk to each other.
+    pub app_id: String,
+    /// Relay servers are used when no direct connection between the peers is possible.
+    pub relay_servers: Vec<Url>,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            rendezvous_url: default_rendezvous_url(),
+            app_id: transfer::APPID.to_string(),
+            relay_servers: vec![default_relay_server()],
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// Checks that the settings can be used to establish a connection.
+    pub fn validate(&self) -> Result<(), PortalError> {
+        if self.app_id.trim().is_empty() {
+            return Err(PortalError::EmptyAppId);
+        }
+        self.relay_hints().map(|_| ())
+    }
+
+    pub(crate) fn app_config(&self) -> AppConfig<AppVersion> {
+        AppConfig {
+            id: AppID::new(self.app_id.clone()),
+            rendezvous_url: self.rendezvous_url.to_string().into(),
+            app_version: AppVersion::ours(),
+        }
+    }
+
+    pub(crate) fn relay_hints(&self) -> Result<Vec<RelayHint>, PortalError> {
+        Ok(relay_hints(&self.relay_servers)?)
+    }
+}
+
+fn default_rendezvous_url() -> Url {
+    DEFAULT_RENDEZVOUS_SERVER
+        .parse()
+        .expect("constant URL should be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_settings_match_upstream_app_config() {
+        let app_config = ConnectionSettings::default().app_config();
+        assert_eq!(app_config.id, transfer::APP_CONFIG.id);
+        assert_eq!(
+            app_config.rendezvous_url,
+            transfer::APP_CONFIG.rendezvous_url
+        );
+    }
+
+    #[test]
+    fn rejects_relay_servers_with_unknown_scheme() {
+        let settings = ConnectionSettings {
+            relay_servers: vec![Url::parse("http://relay.example.com:4001").expect("valid URL")],
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_empty_app_id() {
+        let settings = ConnectionSettings {
+            app_id: " ".to_owned(),
+            ..Default::default()
+        };
+        assert!(matches!(settings.validate(), Err(PortalError::EmptyAppId)));
+    }
+}
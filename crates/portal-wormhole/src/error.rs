@@ -1,40 +1,101 @@
 use crate::cancellation::CancellationError;
 use futures::stream::Aborted;
 use magic_wormhole::transfer::TransferError;
+use magic_wormhole::transit::{RelayHintParseError, TransitConnectError, TransitError};
 use magic_wormhole::WormholeError;
 use thiserror::Error;
 
+/// The errors of `magic_wormhole` are boxed, they would make every `Result` several times larger otherwise.
 #[derive(Error, Debug)]
 pub enum PortalError {
     #[error(transparent)]
-    Wormhole(#[from] WormholeError),
+    Wormhole(Box<WormholeError>),
     #[error(transparent)]
-    WormholeTransfer(TransferError),
+    WormholeTransfer(Box<TransferError>),
     #[error("Transfer rejected by peer")]
-    TransferRejected(TransferError),
+    TransferRejected(Box<TransferError>),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Walkdir(#[from] walkdir::Error),
     #[error(transparent)]
     Zip(#[from] zip::result::ZipError),
+    #[error("Unable to detect the Downloads folder")]
+    MissingDownloadsDirectory,
+    #[error("The archive contains an unsafe path: \"{0}\"")]
+    UnsafeArchivePath(String),
+    #[error("The app id must not be empty")]
+    EmptyAppId,
+    #[error("Invalid relay server: {0}")]
+    InvalidRelayServer(#[from] RelayHintParseError),
     #[error("The operation has been canceled")]
     Canceled,
+    #[error("The partially received file doesn't match the file being sent")]
+    ResumeMismatch,
+    #[error("The received file doesn't match the file that has been sent")]
+    ContentHashMismatch,
+    #[error("Not enough free disk space to receive the file ({required} bytes needed, {available} bytes available)")]
+    InsufficientSpace { required: u64, available: u64 },
 }
 
-const TRANSFER_REJECTED_MESSAGE: &str = "transfer rejected";
+impl PortalError {
+    /// A short, stable name for the kind of error, intended for machine-readable output.
+    pub fn category(&self) -> &'static str {
+        match self {
+            PortalError::Wormhole(_) => "wormhole",
+            PortalError::WormholeTransfer(_) => "transfer",
+            PortalError::TransferRejected(_) => "rejected",
+            PortalError::Io(_) | PortalError::Walkdir(_) => "io",
+            PortalError::Zip(_) => "zip",
+            PortalError::MissingDownloadsDirectory => "missing_downloads_directory",
+            PortalError::UnsafeArchivePath(_) => "unsafe_archive_path",
+            PortalError::EmptyAppId => "empty_app_id",
+            PortalError::InvalidRelayServer(_) => "invalid_relay_server",
+            PortalError::Canceled => "canceled",
+            PortalError::ResumeMismatch => "resume_mismatch",
+            PortalError::ContentHashMismatch => "content_hash_mismatch",
+            PortalError::InsufficientSpace { .. } => "insufficient_space",
+        }
+    }
+}
+
+pub(crate) const TRANSFER_REJECTED_MESSAGE: &str = "transfer rejected";
+
+impl From<WormholeError> for PortalError {
+    fn from(value: WormholeError) -> Self {
+        PortalError::Wormhole(Box::new(value))
+    }
+}
 
 impl From<TransferError> for PortalError {
     fn from(value: TransferError) -> Self {
         match value {
             TransferError::PeerError(ref message) if message == TRANSFER_REJECTED_MESSAGE => {
-                PortalError::TransferRejected(value)
+                PortalError::TransferRejected(Box::new(value))
             }
-            _ => PortalError::WormholeTransfer(value),
+            _ => PortalError::WormholeTransfer(Box::new(value)),
         }
     }
 }
 
+impl From<TransitConnectError> for PortalError {
+    fn from(value: TransitConnectError) -> Self {
+        TransferError::from(value).into()
+    }
+}
+
+impl From<TransitError> for PortalError {
+    fn from(value: TransitError) -> Self {
+        TransferError::from(value).into()
+    }
+}
+
+impl From<serde_json::Error> for PortalError {
+    fn from(value: serde_json::Error) -> Self {
+        TransferError::from(value).into()
+    }
+}
+
 impl From<Aborted> for PortalError {
     fn from(_: Aborted) -> Self {
         PortalError::Canceled
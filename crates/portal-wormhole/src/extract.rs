@@ -0,0 +1,302 @@
+use crate::cancellation::CancellationToken;
+use crate::fs::{mark_as_downloaded, open_with_conflict_resolution, sanitize_file_name};
+use crate::PortalError;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Whether a received file is an archive that we know how to extract.
+pub(crate) fn is_extractable(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Extracts a Zip archive into a new folder next to it that is named after the archive.
+/// The archive is removed once it has been extracted successfully.
+///
+/// All entries are validated before anything is written, so an archive
+/// with unsafe paths is rejected as a whole. Entries whose sanitized names collide
+/// with an entry that has already been extracted get a counter added to their name.
+pub(crate) fn extract_zip(
+    archive_path: &Path,
+    mut progress_handler: impl FnMut(u64, u64),
+    cancellation: CancellationToken,
+) -> Result<PathBuf, PortalError> {
+    let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+
+    let mut entries = Vec::with_capacity(archive.len());
+    let mut total = 0;
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        if !entry.is_symlink() && !is_root_directory(entry.name()) {
+            entries.push((index, safe_entry_path(entry.name())?, entry.is_dir()));
+            total += entry.size();
+        }
+    }
+
+    let folder_path = create_folder(&archive_path.with_extension(""))?;
+    let mut paths = ExtractedPaths::new(folder_path.clone());
+    let mut extracted = 0;
+    progress_handler(extracted, total);
+
+    let result = entries
+        .into_iter()
+        .try_for_each(|(index, relative_path, is_dir)| {
+            cancellation.error_if_canceled()?;
+            if is_dir {
+                paths.directory(&relative_path)?;
+                return Ok(());
+            }
+            let (mut file, path) = paths.create_file(&relative_path)?;
+            copy(
+                &mut archive.by_index(index)?,
+                &mut file,
+                &cancellation,
+                |n| {
+                    extracted += n;
+                    progress_handler(extracted, total);
+                },
+            )?;
+            mark_as_downloaded(&path);
+            Ok(())
+        });
+
+    match result {
+        Ok(()) => {
+            fs::remove_file(archive_path)?;
+            Ok(folder_path)
+        }
+        Err(error) => {
+            _ = fs::remove_dir_all(&folder_path);
+            Err(error)
+        }
+    }
+}
+
+fn create_folder(base_path: &Path) -> Result<PathBuf, PortalError> {
+    Ok(open_with_conflict_resolution(base_path, |path| {
+        fs::create_dir(path).map(|_| path.to_owned())
+    })?)
+}
+
+/// Keeps track of where the directories of the archive have been extracted to,
+/// so that the entries in a directory that has been renamed follow it.
+struct ExtractedPaths {
+    folder_path: PathBuf,
+    directories: HashMap<PathBuf, PathBuf>,
+}
+
+impl ExtractedPaths {
+    fn new(folder_path: PathBuf) -> Self {
+        ExtractedPaths {
+            folder_path,
+            directories: HashMap::new(),
+        }
+    }
+
+    /// Creates the directory and its parents unless they have been created already.
+    fn directory(&mut self, relative_path: &Path) -> io::Result<PathBuf> {
+        let mut path = self.folder_path.clone();
+        let mut relative = PathBuf::new();
+        for component in relative_path.components() {
+            relative.push(component);
+            path = match self.directories.get(&relative) {
+                Some(directory) => directory.clone(),
+                None => {
+                    let directory = open_with_conflict_resolution(&path.join(component), |path| {
+                        fs::create_dir(path).map(|()| path.to_owned())
+                    })?;
+                    self.directories.insert(relative.clone(), directory.clone());
+                    directory
+                }
+            };
+        }
+        Ok(path)
+    }
+
+    fn create_file(&mut self, relative_path: &Path) -> io::Result<(File, PathBuf)> {
+        let directory = self.directory(relative_path.parent().unwrap_or(Path::new("")))?;
+        let file_name = relative_path
+            .file_name()
+            .expect("safe entry paths to end with a file name");
+        open_with_conflict_resolution(&directory.join(file_name), |path| {
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .map(|file| (file, path.to_owned()))
+        })
+    }
+}
+
+fn copy(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    cancellation: &CancellationToken,
+    mut on_progress: impl FnMut(u64),
+) -> Result<(), PortalError> {
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        cancellation.error_if_canceled()?;
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buffer[..n])?;
+        on_progress(n as u64);
+    }
+}
+
+/// Turns the name of an entry into a relative path that stays inside the extraction folder.
+/// Both `/` and `\` are treated as separators as archives created on Windows may use either.
+fn safe_entry_path(name: &str) -> Result<PathBuf, PortalError> {
+    let unsafe_path = || PortalError::UnsafeArchivePath(name.to_owned());
+
+    if name.starts_with(['/', '\\']) || has_drive_prefix(name) {
+        return Err(unsafe_path());
+    }
+
+    let mut path = PathBuf::new();
+    for component in name.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => return Err(unsafe_path()),
+            component => path.push(sanitize_file_name(component, "_").as_ref()),
+        }
+    }
+
+    if path.as_os_str().is_empty() {
+        Err(unsafe_path())
+    } else {
+        Ok(path)
+    }
+}
+
+/// Archives packed by Portal contain an entry for the root folder itself, named `/`.
+fn is_root_directory(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| matches!(c, '/' | '\\'))
+}
+
+fn has_drive_prefix(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(
+        (chars.next(), chars.next()),
+        (Some(letter), Some(':')) if letter.is_ascii_alphabetic()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancellation::CancellationSource;
+    use tempfile::TempDir;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn accepts_relative_paths() {
+        assert_eq!(
+            safe_entry_path("folder/./sub\\file.txt").expect("path to be safe"),
+            Path::new("folder").join("sub").join("file.txt")
+        );
+    }
+
+    #[test]
+    fn rejects_traversal_and_absolute_paths() {
+        for name in [
+            "../file.txt",
+            "folder/../../file.txt",
+            "/etc/passwd",
+            "\\windows\\system32",
+            "C:\\file.txt",
+            "c:file.txt",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    safe_entry_path(name),
+                    Err(PortalError::UnsafeArchivePath(_))
+                ),
+                "{name} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn extracts_into_conflict_resolved_folder() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let archive_path = temp_dir.path().join("folder.zip");
+        write_archive(
+            &archive_path,
+            &[("/", ""), ("folder/a.txt", "a"), ("folder/sub/b.txt", "b")],
+        );
+        fs::create_dir(temp_dir.path().join("folder")).expect("folder to be created");
+
+        let folder_path = extract_zip(&archive_path, |_, _| {}, cancellation_token())
+            .expect("extraction to succeed");
+
+        assert_eq!(folder_path, temp_dir.path().join("folder (1)"));
+        assert_eq!(
+            fs::read_to_string(folder_path.join("folder/sub/b.txt")).expect("file to exist"),
+            "b"
+        );
+        assert!(!archive_path.exists());
+    }
+
+    #[test]
+    fn extracts_entries_with_colliding_names() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let archive_path = temp_dir.path().join("folder.zip");
+        write_archive(
+            &archive_path,
+            &[
+                ("a_b.txt", "first"),
+                ("a\u{1}b.txt", "second"),
+                ("sub", "file"),
+                ("sub/c.txt", "c"),
+            ],
+        );
+
+        let folder_path = extract_zip(&archive_path, |_, _| {}, cancellation_token())
+            .expect("extraction to succeed");
+
+        let read = |path: &str| fs::read_to_string(folder_path.join(path)).expect("file to exist");
+        assert_eq!(read("a_b.txt"), "first");
+        assert_eq!(read("a_b (1).txt"), "second");
+        assert_eq!(read("sub"), "file");
+        assert_eq!(read("sub (1)/c.txt"), "c");
+    }
+
+    #[test]
+    fn rejects_archive_with_unsafe_entry() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let archive_path = temp_dir.path().join("evil.zip");
+        write_archive(&archive_path, &[("a.txt", "a"), ("../b.txt", "b")]);
+
+        let result = extract_zip(&archive_path, |_, _| {}, cancellation_token());
+
+        assert!(matches!(result, Err(PortalError::UnsafeArchivePath(_))));
+        assert!(archive_path.exists());
+        assert!(!temp_dir.path().join("evil").exists());
+        assert!(!temp_dir.path().join("b.txt").exists());
+    }
+
+    fn write_archive(path: &Path, entries: &[(&str, &str)]) {
+        let mut writer = ZipWriter::new(File::create(path).expect("archive to be created"));
+        for (name, contents) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .expect("entry to be started");
+            writer
+                .write_all(contents.as_bytes())
+                .expect("entry to be written");
+        }
+        writer.finish().expect("archive to be finished");
+    }
+
+    fn cancellation_token() -> CancellationToken {
+        CancellationSource::default().token()
+    }
+}
@@ -4,4 +4,8 @@ mod persist;
 pub use self::persist::*;
 mod download;
 pub use self::download::*;
+mod free_space;
+pub use self::free_space::*;
 mod path_parts;
+mod preallocate;
+pub(crate) use self::preallocate::*;
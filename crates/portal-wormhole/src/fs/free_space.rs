@@ -0,0 +1,51 @@
+use std::path::Path;
+
+#[cfg(unix)]
+use self::unix::available_space_impl;
+
+#[cfg(not(unix))]
+use self::generic::available_space_impl;
+
+/// The number of bytes that can be written to the file system containing `path`
+/// or `None` if that can't be determined.
+/// If `path` doesn't exist yet, the space of its closest existing ancestor is returned.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|path| path.exists())?;
+    available_space_impl(existing)
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::path::Path;
+
+    pub(super) fn available_space_impl(path: &Path) -> Option<u64> {
+        let stat = rustix::fs::statvfs(path).ok()?;
+        // Blocks reserved for the super-user are not available to us.
+        Some(stat.f_bavail.saturating_mul(stat.f_frsize))
+    }
+}
+
+#[cfg(not(unix))]
+mod generic {
+    use std::path::Path;
+
+    pub(super) fn available_space_impl(_path: &Path) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn uses_closest_existing_ancestor() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let available = available_space(temp_dir.path()).expect("space to be known");
+        let missing = temp_dir.path().join("missing/folder");
+        let available_in_missing = available_space(&missing).expect("space to be known");
+        // Other processes may write to the same disk in the meantime.
+        assert!(available.abs_diff(available_in_missing) < 1024 * 1024 * 1024);
+    }
+}
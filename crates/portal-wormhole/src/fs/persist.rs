@@ -1,6 +1,7 @@
 use super::path_parts::PathParts;
+use std::fs;
 use std::io::{self, ErrorKind};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tailcall::tailcall;
 
 pub fn open_with_conflict_resolution<T>(
@@ -27,11 +28,30 @@ pub fn open_with_conflict_resolution<T>(
     }
 }
 
+/// Moves the file at `source` to `path`, or to `path` with a counter if that already exists.
+/// Existing files are never replaced.
+pub fn rename_with_conflict_resolution(source: &Path, path: &Path) -> io::Result<PathBuf> {
+    open_with_conflict_resolution(path, |path| {
+        rename_no_replace(source, path).map(|()| path.to_owned())
+    })
+}
+
+fn rename_no_replace(source: &Path, target: &Path) -> io::Result<()> {
+    // Unlike renaming, linking fails atomically if the target exists.
+    match fs::hard_link(source, target) {
+        Ok(()) => fs::remove_file(source),
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => Err(error),
+        // Not every file system supports hard links.
+        Err(_) if target.symlink_metadata().is_ok() => Err(ErrorKind::AlreadyExists.into()),
+        Err(_) => fs::rename(source, target),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::VecDeque;
-    use std::path::PathBuf;
+    use tempfile::TempDir;
     use thiserror::Error;
 
     #[test]
@@ -57,6 +77,24 @@ mod tests {
         assert_eq!(path, expected_path);
     }
 
+    #[test]
+    fn renames_without_replacing_existing_files() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let source = temp_dir.path().join("foo.txt.part");
+        let existing = temp_dir.path().join("foo.txt");
+        fs::write(&source, "new").expect("file to be written");
+        fs::write(&existing, "existing").expect("file to be written");
+
+        let path = rename_with_conflict_resolution(&source, &existing).expect("rename to succeed");
+        assert_eq!(path, temp_dir.path().join("foo (1).txt"));
+        assert_eq!(fs::read_to_string(&path).expect("file to be read"), "new");
+        assert_eq!(
+            fs::read_to_string(&existing).expect("file to be read"),
+            "existing"
+        );
+        assert!(!source.exists());
+    }
+
     fn open(mut existing_paths: VecDeque<PathBuf>) -> impl FnMut(&Path) -> io::Result<PathBuf> {
         move |path| match existing_paths.pop_front() {
             None => Ok(path.to_owned()),
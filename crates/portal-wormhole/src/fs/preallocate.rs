@@ -0,0 +1,81 @@
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) use self::linux::*;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) use self::generic::*;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod linux {
+    //! The space is reserved with `fallocate`, which keeps the size of the file,
+    //! so that a partially received file still ends after the received bytes.
+    use async_std::fs::File;
+    use rustix::fs::{fallocate, ftruncate, FallocateFlags};
+    use rustix::io::Errno;
+    use std::io;
+
+    /// Reserves disk space for the first `len` bytes of the file, so that it isn't fragmented
+    /// while it's written. File systems that can't do this are ignored.
+    pub(crate) fn preallocate(file: &File, len: u64) -> io::Result<()> {
+        match fallocate(file, FallocateFlags::KEEP_SIZE, 0, len) {
+            Ok(()) | Err(Errno::OPNOTSUPP | Errno::NOSYS) => Ok(()),
+            Err(errno) => Err(errno.into()),
+        }
+    }
+
+    /// Releases the space reserved after the first `len` bytes, e.g. of a partially received file
+    /// that is kept to resume the transfer, which may never happen.
+    /// Truncating frees the blocks after the end of the file, unlike punching a hole
+    /// which ext4 limits to the size of the file.
+    pub(crate) fn release_preallocated(file: &File, len: u64) -> io::Result<()> {
+        Ok(ftruncate(file, len)?)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use async_std::task::block_on;
+        use futures::AsyncWriteExt;
+        use std::os::unix::fs::MetadataExt;
+        use tempfile::TempDir;
+
+        #[test]
+        fn releases_space_after_received_bytes() {
+            const LEN: u64 = 1024 * 1024;
+            let temp_dir = TempDir::new().expect("temp dir to be created");
+            let path = temp_dir.path().join("file.bin.part");
+            let mut file = File::from(std::fs::File::create(&path).expect("file to be created"));
+            let allocated = || {
+                std::fs::metadata(&path)
+                    .expect("metadata to be read")
+                    .blocks()
+                    * 512
+            };
+
+            preallocate(&file, LEN).expect("space to be reserved");
+            if allocated() < LEN {
+                // The file system can't reserve space.
+                return;
+            }
+            block_on(file.write_all(&[0x5a; 4096])).expect("file to be written");
+            block_on(file.flush()).expect("file to be flushed");
+
+            release_preallocated(&file, 4096).expect("space to be released");
+            assert!(allocated() < LEN);
+            assert_eq!(std::fs::read(&path).expect("file to be read"), [0x5a; 4096]);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod generic {
+    use async_std::fs::File;
+    use std::io;
+
+    pub(crate) fn preallocate(_file: &File, _len: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub(crate) fn release_preallocated(_file: &File, _len: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -2,15 +2,29 @@ mod error;
 pub mod receive;
 pub use self::error::*;
 mod cancellation;
+mod code_entry;
+pub use self::code_entry::{code_completions, complete_code, normalize_code, unknown_words};
+mod connection;
+pub use self::connection::*;
+mod extract;
 mod fs;
+mod link;
+pub use self::link::{parse_link, share_link};
+mod protocol;
+mod rate;
+pub use self::rate::TransferRate;
+mod resume;
 pub mod send;
 mod sync;
-mod temp_zip;
 mod transit;
+mod verifier;
+pub use self::verifier::Verifier;
+mod zip_stream;
 
 pub use magic_wormhole::transit::{ConnectionType, TransitInfo};
 pub use magic_wormhole::uri::WormholeTransferUri;
 pub use magic_wormhole::Code;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use trait_set::trait_set;
 use url::Url;
@@ -19,18 +33,32 @@ trait_set! {
     pub trait RequestRepaint = FnMut() + Clone + Send + Sync + 'static;
 }
 
-#[derive(Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct Progress {
     pub value: u64,
     pub total: u64,
 }
 
+/// The SHA-256 digest of a transferred file. For folders, this is the digest of the Zip file
+/// in which they are transferred.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentHash {
+    /// The hex-encoded digest.
+    pub sha256: String,
+    /// Whether both peers have computed the same digest.
+    /// Senders always compare it with the receiver's checksum,
+    /// receivers only get the sender's digest if the sender uses Portal as well.
+    pub verified: bool,
+}
+
 #[non_exhaustive]
 pub struct SharableWormholeTransferUri {
     pub code: Code,
 }
 
 impl SharableWormholeTransferUri {
+    pub(crate) const HOST: &'static str = "wormhole-transfer.link";
+
     pub fn new(code: Code) -> Self {
         Self { code }
     }
@@ -38,8 +66,8 @@ impl SharableWormholeTransferUri {
 
 impl From<&SharableWormholeTransferUri> for Url {
     fn from(value: &SharableWormholeTransferUri) -> Self {
-        let mut url =
-            Url::parse("https://wormhole-transfer.link").expect("constant URL should be valid");
+        let mut url = Url::parse(&format!("https://{}", SharableWormholeTransferUri::HOST))
+            .expect("constant URL should be valid");
         url.set_fragment(Some(&value.code));
         url
     }
@@ -0,0 +1,100 @@
+use crate::{ConnectionSettings, SharableWormholeTransferUri};
+use magic_wormhole::uri::WormholeTransferUri;
+use magic_wormhole::Code;
+use std::str::FromStr;
+use url::Url;
+
+/// Parses a link that the user has pasted instead of a code:
+/// Either a `wormhole-transfer:` URI or a share link as produced by [`SharableWormholeTransferUri`].
+/// Returns `None` for anything else, e.g. a plain code.
+pub fn parse_link(input: &str) -> Option<WormholeTransferUri> {
+    let url = Url::parse(input.trim()).ok()?;
+    match url.scheme() {
+        "wormhole-transfer" => WormholeTransferUri::try_from(&url).ok(),
+        "http" | "https" if url.host_str() == Some(SharableWormholeTransferUri::HOST) => {
+            let code = percent_encoding::percent_decode_str(url.fragment()?)
+                .decode_utf8()
+                .ok()?;
+            Code::from_str(&code).ok().map(WormholeTransferUri::new)
+        }
+        _ => None,
+    }
+}
+
+/// The link with which receivers connect to us using the code.
+/// Share links don't know about rendezvous servers, so a `wormhole-transfer:` URI
+/// that names the server is used unless it's the default server.
+pub fn share_link(code: Code, rendezvous_url: &Url) -> String {
+    if *rendezvous_url == ConnectionSettings::default().rendezvous_url {
+        SharableWormholeTransferUri::new(code).to_string()
+    } else {
+        let mut uri = WormholeTransferUri::new(code);
+        uri.rendezvous_server = Some(rendezvous_url.clone());
+        uri.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_share_link() {
+        let uri = parse_link("https://wormhole-transfer.link/#7-guitarist-revenge")
+            .expect("share link should be parsed");
+        assert_eq!(uri.code.to_string(), "7-guitarist-revenge");
+        assert_eq!(uri.rendezvous_server, None);
+        assert!(!uri.is_leader);
+    }
+
+    #[test]
+    fn parses_share_link_produced_by_portal() {
+        let code = Code::from_str("7-guitarist-revenge").expect("code should be valid");
+        let link = SharableWormholeTransferUri::new(code.clone()).to_string();
+        let uri = parse_link(&link).expect("share link should be parsed");
+        assert_eq!(uri.code, code);
+    }
+
+    #[test]
+    fn parses_wormhole_transfer_uri_with_rendezvous_server() {
+        let uri = parse_link(
+            " wormhole-transfer:7-guitarist-revenge?rendezvous=ws%3A%2F%2Fexample.com%3A4000%2Fv1\n",
+        )
+        .expect("URI should be parsed");
+        assert_eq!(uri.code.to_string(), "7-guitarist-revenge");
+        assert_eq!(
+            uri.rendezvous_server.map(String::from).as_deref(),
+            Some("ws://example.com:4000/v1")
+        );
+    }
+
+    #[test]
+    fn share_link_keeps_custom_rendezvous_server() {
+        let code = Code::from_str("7-guitarist-revenge").expect("code should be valid");
+        let rendezvous_url = Url::parse("ws://example.com:4000/v1").expect("URL should be valid");
+
+        let link = share_link(code.clone(), &rendezvous_url);
+        assert!(link.starts_with("wormhole-transfer:"), "{link}");
+        let uri = parse_link(&link).expect("link should be parsed");
+        assert_eq!(uri.code, code);
+        assert_eq!(uri.rendezvous_server, Some(rendezvous_url));
+
+        let default_link = share_link(code, &ConnectionSettings::default().rendezvous_url);
+        assert_eq!(
+            default_link,
+            "https://wormhole-transfer.link/#7-guitarist-revenge"
+        );
+    }
+
+    #[test]
+    fn ignores_codes_and_other_links() {
+        for input in [
+            "7-guitarist-revenge",
+            "https://wormhole-transfer.link/",
+            "https://example.com/#7-guitarist-revenge",
+            "wormhole-transfer:",
+        ] {
+            assert!(parse_link(input).is_none(), "{input} should be ignored");
+        }
+    }
+}
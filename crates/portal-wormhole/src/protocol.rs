@@ -0,0 +1,663 @@
+//! The messages of the file transfer protocol (v1) as they are exchanged over the wormhole.
+//!
+//! `magic_wormhole` only exposes a high-level API for files which rejects text messages,
+//! so the parts of the protocol that we need are implemented here.
+//! Resuming transfers and checking content hashes need access to the transit connection as well,
+//! which is why the few functions below that set it up use `magic_wormhole`'s deprecated transit API.
+
+use crate::error::{PortalError, TRANSFER_REJECTED_MESSAGE};
+use crate::transit::ProgressHandler;
+use crate::ContentHash;
+use futures::future::{select, Either};
+use futures::{pin_mut, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Future};
+use magic_wormhole::transfer::TransferError;
+#[allow(deprecated)]
+use magic_wormhole::transit::TransitKey;
+use magic_wormhole::transit::{self, Abilities, Hints, RelayHint, Transit, TransitError};
+use magic_wormhole::{Key, Wormhole};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// The transit API is deprecated in `magic_wormhole` as it's considered an implementation detail,
+/// but we need it to implement the protocol.
+#[allow(deprecated)]
+pub(crate) type TransitConnector = transit::TransitConnector;
+
+const TRANSFER_CANCELED_MESSAGE: &str = "transfer cancelled";
+
+/// The only mode of directory offers that the Python client understands:
+/// a Zip file with deflated entries.
+pub(crate) const DIRECTORY_MODE: &str = "zipfile/deflated";
+
+/// The abilities of the file transfer protocol that peers exchange when they connect,
+/// extended by the extensions of the protocol that we understand.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct AppVersion {
+    #[serde(default)]
+    abilities: Vec<String>,
+    #[serde(default)]
+    portal_abilities: Vec<String>,
+}
+
+/// Senders with this ability send [`PeerMessage::ContentHash`] after the transfer.
+const CONTENT_HASH_ABILITY: &str = "content-hash";
+
+impl AppVersion {
+    pub(crate) fn ours() -> Self {
+        AppVersion {
+            abilities: vec!["transfer-v1".to_owned()],
+            portal_abilities: vec![CONTENT_HASH_ABILITY.to_owned()],
+        }
+    }
+
+    fn of_peer(wormhole: &Wormhole) -> Self {
+        serde_json::from_value(wormhole.peer_version().clone()).unwrap_or_default()
+    }
+
+    fn supports_content_hash(&self) -> bool {
+        self.portal_abilities
+            .iter()
+            .any(|ability| ability == CONTENT_HASH_ABILITY)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum PeerMessage {
+    Transit(TransitMessage),
+    Offer(OfferMessage),
+    Answer(AnswerMessage),
+    Error(String),
+    /// Our extension of the protocol, sent instead of an error when the prefix of [`AnswerMessage::FileResume`]
+    /// doesn't match the file, which only peers that answer with it receive.
+    ResumeRejected(String),
+    /// Our extension of the protocol, only sent to peers that announce [`CONTENT_HASH_ABILITY`].
+    ContentHash(ContentHashMessage),
+    #[serde(other)]
+    Unknown,
+}
+
+impl PeerMessage {
+    pub(crate) fn transit(connector: &TransitConnector) -> Self {
+        PeerMessage::Transit(TransitMessage {
+            abilities_v1: *connector.our_abilities(),
+            hints_v1: (**connector.our_hints()).clone(),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            PeerMessage::Transit(_) => "transit",
+            PeerMessage::Offer(_) => "offer",
+            PeerMessage::Answer(_) => "answer",
+            PeerMessage::Error(_) => "error",
+            PeerMessage::ResumeRejected(_) => "resume-rejected",
+            PeerMessage::ContentHash(_) => "content-hash",
+            PeerMessage::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct TransitMessage {
+    pub(crate) abilities_v1: Abilities,
+    pub(crate) hints_v1: Hints,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum OfferMessage {
+    Message(String),
+    File {
+        filename: String,
+        filesize: u64,
+        /// Our extension of the protocol: The sender understands [`AnswerMessage::FileResume`].
+        /// Other clients ignore unknown fields.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        resumable: bool,
+    },
+    Directory {
+        dirname: String,
+        mode: String,
+        zipsize: u64,
+        numbytes: u64,
+        numfiles: u64,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AnswerMessage {
+    MessageAck(String),
+    FileAck(String),
+    /// Accepts a resumable offer, of which the first `offset` bytes have already been received.
+    FileResume {
+        offset: u64,
+        sha256: String,
+    },
+}
+
+/// Sent by the sender once the file has been sent,
+/// so that the receiver can verify that it received the same file.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ContentHashMessage {
+    sha256: String,
+}
+
+/// Sent by the receiver over the transit connection once the file has been received.
+#[derive(Serialize, Deserialize, Debug)]
+struct TransitAck {
+    ack: String,
+    sha256: String,
+}
+
+pub(crate) async fn send_message(
+    wormhole: &mut Wormhole,
+    message: &PeerMessage,
+) -> Result<(), PortalError> {
+    Ok(wormhole.send_json(message).await?)
+}
+
+/// Receives the next message, turning error messages from the peer into errors.
+pub(crate) async fn receive_message(wormhole: &mut Wormhole) -> Result<PeerMessage, PortalError> {
+    match wormhole
+        .receive_json::<PeerMessage>()
+        .await?
+        .map_err(TransferError::from)?
+    {
+        PeerMessage::Error(message) => Err(TransferError::PeerError(message).into()),
+        // It's our partial file that's outdated.
+        PeerMessage::ResumeRejected(_) => Err(PortalError::ResumeMismatch),
+        message => Ok(message),
+    }
+}
+
+pub(crate) fn unexpected_message(expected: &str, message: &PeerMessage) -> PortalError {
+    TransferError::ProtocolUnexpectedMessage(expected.into(), message.name().into()).into()
+}
+
+#[allow(deprecated)]
+pub(crate) async fn init_transit(
+    relay_hints: Vec<RelayHint>,
+) -> Result<TransitConnector, PortalError> {
+    Ok(transit::init(Abilities::ALL, None, relay_hints).await?)
+}
+
+/// Whether we can unpack a directory offer with this mode.
+pub(crate) fn is_supported_directory_mode(mode: &str) -> bool {
+    mode == DIRECTORY_MODE
+}
+
+#[allow(deprecated)]
+pub(crate) fn transit_key(wormhole: &Wormhole) -> Key<TransitKey> {
+    wormhole.key().derive_transit_key(wormhole.appid())
+}
+
+/// The records of a transit connection.
+/// Transfers can be tested with a local stand-in instead of a network connection.
+pub(crate) trait Records {
+    fn receive_record(&mut self) -> impl Future<Output = Result<Box<[u8]>, TransitError>> + Send;
+
+    fn send_record(
+        &mut self,
+        record: &[u8],
+    ) -> impl Future<Output = Result<(), TransitError>> + Send;
+
+    fn flush(&mut self) -> impl Future<Output = Result<(), TransitError>> + Send;
+}
+
+impl Records for Transit {
+    fn receive_record(&mut self) -> impl Future<Output = Result<Box<[u8]>, TransitError>> + Send {
+        Transit::receive_record(self)
+    }
+
+    fn send_record(
+        &mut self,
+        record: &[u8],
+    ) -> impl Future<Output = Result<(), TransitError>> + Send {
+        Transit::send_record(self, record)
+    }
+
+    fn flush(&mut self) -> impl Future<Output = Result<(), TransitError>> + Send {
+        Transit::flush(self)
+    }
+}
+
+/// The beginning of a file that the receiver already has from an interrupted transfer.
+/// The checksum in the final acknowledgement covers the whole file, so both sides hash the prefix.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Prefix {
+    pub(crate) size: u64,
+    hasher: Sha256,
+}
+
+impl Prefix {
+    /// Reads and hashes the first `size` bytes.
+    pub(crate) async fn read(reader: &mut (impl AsyncRead + Unpin), size: u64) -> io::Result<Self> {
+        let mut prefix = Prefix::default();
+        let mut buffer = vec![0; 64 * 1024];
+        while prefix.size < size {
+            let length = buffer.len().min((size - prefix.size) as usize);
+            let n = reader.read(&mut buffer[..length]).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            prefix.update(&buffer[..n]);
+        }
+        Ok(prefix)
+    }
+
+    pub(crate) fn sha256(&self) -> String {
+        hex::encode(self.hasher.clone().finalize())
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+        self.size += bytes.len() as u64;
+    }
+}
+
+/// Reads the prefix that the receiver asks to resume after and checks that the receiver has the same bytes.
+pub(crate) async fn resumed_prefix(
+    reader: &mut (impl AsyncRead + Unpin),
+    offset: u64,
+    sha256: &str,
+) -> Result<Prefix, PortalError> {
+    match Prefix::read(reader, offset).await {
+        Ok(prefix) if prefix.sha256() == sha256 => Ok(prefix),
+        Ok(_) => Err(PortalError::ResumeMismatch),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+            Err(PortalError::ResumeMismatch)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Receives the rest of the `file_size` bytes after the prefix and acknowledges them with their checksum.
+/// The prefix is updated with every record that has been written, so that an interrupted
+/// transfer can be resumed.
+pub(crate) async fn receive_records(
+    transit: &mut impl Records,
+    file_size: u64,
+    prefix: &mut Prefix,
+    mut progress_handler: impl ProgressHandler,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> Result<(), PortalError> {
+    progress_handler(prefix.size, file_size);
+
+    while prefix.size < file_size {
+        let record = transit.receive_record().await?;
+        let received = prefix.size + record.len() as u64;
+        if received > file_size {
+            return Err(TransferError::FileSize {
+                sent_size: received,
+                file_size,
+            }
+            .into());
+        }
+
+        writer.write_all(&record).await?;
+        prefix.update(&record);
+        progress_handler(prefix.size, file_size);
+    }
+    // Closing an async-std file does not flush it.
+    writer.flush().await?;
+    writer.close().await?;
+
+    let ack = TransitAck {
+        ack: "ok".to_owned(),
+        sha256: prefix.sha256(),
+    };
+    transit.send_record(&serde_json::to_vec(&ack)?).await?;
+    Ok(())
+}
+
+/// Sends the rest of the `file_size` bytes after the prefix (which has already been read)
+/// and waits for the receiver to acknowledge their checksum, which is returned.
+pub(crate) async fn send_records(
+    transit: &mut impl Records,
+    file_size: u64,
+    prefix: Prefix,
+    mut progress_handler: impl ProgressHandler,
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<String, PortalError> {
+    let Prefix {
+        size: mut sent,
+        mut hasher,
+    } = prefix;
+    let mut buffer = vec![0; 16 * 1024];
+
+    progress_handler(sent, file_size);
+
+    loop {
+        let n = reader.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        transit.send_record(&buffer[..n]).await?;
+        hasher.update(&buffer[..n]);
+        sent += n as u64;
+        progress_handler(sent, file_size);
+    }
+    transit.flush().await?;
+
+    if sent != file_size {
+        return Err(TransferError::FileSize {
+            sent_size: sent,
+            file_size,
+        }
+        .into());
+    }
+
+    let ack: TransitAck = serde_json::from_slice(&transit.receive_record().await?)?;
+    let sha256 = hex::encode(hasher.finalize());
+    if ack.sha256 == sha256 {
+        Ok(sha256)
+    } else {
+        Err(TransferError::Checksum.into())
+    }
+}
+
+/// Tells receivers that use Portal the digest of the file that has been sent.
+pub(crate) async fn send_content_hash(
+    wormhole: &mut Wormhole,
+    sha256: String,
+) -> Result<ContentHash, PortalError> {
+    if AppVersion::of_peer(wormhole).supports_content_hash() {
+        let message = PeerMessage::ContentHash(ContentHashMessage {
+            sha256: sha256.clone(),
+        });
+        send_message(wormhole, &message).await?;
+    }
+    // The receiver has already acknowledged the same digest.
+    Ok(ContentHash {
+        sha256,
+        verified: true,
+    })
+}
+
+/// Compares the digest of the received file with the digest of the sent file,
+/// if the sender tells us about it.
+pub(crate) async fn verify_content_hash(
+    wormhole: &mut Wormhole,
+    sha256: String,
+) -> Result<ContentHash, PortalError> {
+    if !AppVersion::of_peer(wormhole).supports_content_hash() {
+        return Ok(ContentHash {
+            sha256,
+            verified: false,
+        });
+    }
+    match receive_message(wormhole).await? {
+        PeerMessage::ContentHash(message) if message.sha256 == sha256 => Ok(ContentHash {
+            sha256,
+            verified: true,
+        }),
+        PeerMessage::ContentHash(_) => Err(PortalError::ContentHashMismatch),
+        message => Err(unexpected_message("content-hash", &message)),
+    }
+}
+
+/// Drives `future` to completion unless `cancel` completes first.
+pub(crate) async fn until_canceled<T>(
+    future: impl Future<Output = Result<T, PortalError>>,
+    cancel: impl Future<Output = ()>,
+) -> Result<T, PortalError> {
+    pin_mut!(future);
+    pin_mut!(cancel);
+    match select(future, cancel).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(PortalError::Canceled),
+    }
+}
+
+/// Closes the wormhole at the end of an exchange.
+/// On failure, the peer is told why, unless the failure came from the peer.
+pub(crate) async fn finish<T>(
+    wormhole: Wormhole,
+    result: Result<T, PortalError>,
+) -> Result<T, PortalError> {
+    match result {
+        Ok(value) => {
+            wormhole.close().await?;
+            Ok(value)
+        }
+        Err(error) => {
+            close_with_error(wormhole, &error).await;
+            Err(error)
+        }
+    }
+}
+
+pub(crate) async fn reject(mut wormhole: Wormhole) -> Result<(), PortalError> {
+    send_message(
+        &mut wormhole,
+        &PeerMessage::Error(TRANSFER_REJECTED_MESSAGE.to_owned()),
+    )
+    .await?;
+    Ok(wormhole.close().await?)
+}
+
+async fn close_with_error(mut wormhole: Wormhole, error: &PortalError) {
+    let message = match error {
+        PortalError::TransferRejected(_) => None,
+        PortalError::WormholeTransfer(error) if matches!(**error, TransferError::PeerError(_)) => {
+            None
+        }
+        PortalError::Canceled => Some(PeerMessage::Error(TRANSFER_CANCELED_MESSAGE.to_owned())),
+        PortalError::ResumeMismatch => Some(PeerMessage::ResumeRejected(error.to_string())),
+        error => Some(PeerMessage::Error(error.to_string())),
+    };
+    if let Some(message) = message {
+        _ = send_message(&mut wormhole, &message).await;
+    }
+    _ = wormhole.close().await;
+}
+
+#[cfg(test)]
+pub(crate) mod local_transit;
+
+#[cfg(test)]
+mod tests {
+    use super::local_transit::local_transit;
+    use super::*;
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+    use futures::join;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn file_content() -> Vec<u8> {
+        (0..100_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn resumes_interrupted_transfer() {
+        let content = file_content();
+        let file_size = content.len() as u64;
+        let mut received = Vec::new();
+        let mut prefix = Prefix::default();
+
+        let (mut sending, mut receiving) = local_transit(3);
+        let mut reader = Cursor::new(&content);
+        let (sent, interrupted) = block_on(async {
+            join!(
+                send_records(
+                    &mut sending,
+                    file_size,
+                    Prefix::default(),
+                    |_, _| {},
+                    &mut reader,
+                ),
+                receive_records(
+                    &mut receiving,
+                    file_size,
+                    &mut prefix,
+                    |_, _| {},
+                    &mut received,
+                ),
+            )
+        });
+        assert!(sent.is_err());
+        assert!(interrupted.is_err());
+        assert_eq!(prefix.size, 3 * 16 * 1024);
+        assert_eq!(received, content[..received.len()]);
+
+        let (offset, sha256) = (prefix.size, prefix.sha256());
+        let first_progress = Rc::new(Cell::new(None));
+        let first_progress_handler = {
+            let first_progress = Rc::clone(&first_progress);
+            move |value, _| first_progress.set(first_progress.get().or(Some(value)))
+        };
+        let (mut sending, mut receiving) = local_transit(usize::MAX);
+        let mut reader = Cursor::new(&content);
+        let (sent, resumed) = block_on(async {
+            let sent = async {
+                let resumed_prefix = resumed_prefix(&mut reader, offset, &sha256).await?;
+                send_records(
+                    &mut sending,
+                    file_size,
+                    resumed_prefix,
+                    |_, _| {},
+                    &mut reader,
+                )
+                .await
+            };
+            let resumed = receive_records(
+                &mut receiving,
+                file_size,
+                &mut prefix,
+                first_progress_handler,
+                &mut received,
+            );
+            join!(sent, resumed)
+        });
+        sent.expect("sending to succeed");
+        resumed.expect("receiving to succeed");
+        assert_eq!(received, content);
+        assert_eq!(first_progress.get(), Some(offset));
+    }
+
+    #[test]
+    fn rejects_resume_with_different_prefix() {
+        let content = file_content();
+        let mut other = content.clone();
+        other[10] ^= 1;
+        let other_prefix =
+            block_on(Prefix::read(&mut Cursor::new(&other), 100)).expect("prefix to be read");
+
+        let result = block_on(resumed_prefix(
+            &mut Cursor::new(&content),
+            100,
+            &other_prefix.sha256(),
+        ));
+        assert!(matches!(result, Err(PortalError::ResumeMismatch)));
+
+        let beyond_end = block_on(resumed_prefix(
+            &mut Cursor::new(&content[..50]),
+            100,
+            &other_prefix.sha256(),
+        ));
+        assert!(matches!(beyond_end, Err(PortalError::ResumeMismatch)));
+    }
+
+    #[test]
+    fn parses_file_offer_of_other_clients_as_not_resumable() {
+        let message: PeerMessage =
+            serde_json::from_str(r#"{"offer": {"file": {"filename": "a.txt", "filesize": 3}}}"#)
+                .expect("valid message");
+        assert!(matches!(
+            message,
+            PeerMessage::Offer(OfferMessage::File {
+                resumable: false,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn serializes_file_resume() {
+        let message = PeerMessage::Answer(AnswerMessage::FileResume {
+            offset: 42,
+            sha256: "abc".to_owned(),
+        });
+        assert_eq!(
+            serde_json::to_string(&message).expect("serializable message"),
+            r#"{"answer":{"file_resume":{"offset":42,"sha256":"abc"}}}"#
+        );
+    }
+
+    #[test]
+    fn serializes_content_hash() {
+        let message = PeerMessage::ContentHash(ContentHashMessage {
+            sha256: "abc".to_owned(),
+        });
+        assert_eq!(
+            serde_json::to_string(&message).expect("serializable message"),
+            r#"{"content-hash":{"sha256":"abc"}}"#
+        );
+    }
+
+    #[test]
+    fn detects_content_hash_ability() {
+        let ours = serde_json::to_value(AppVersion::ours()).expect("serializable app version");
+        let ours: AppVersion = serde_json::from_value(ours).expect("valid app version");
+        assert!(ours.supports_content_hash());
+
+        // Other clients, e.g. the Python CLI, only announce the standard abilities.
+        let other: AppVersion =
+            serde_json::from_str(r#"{"abilities": ["transfer-v1"]}"#).expect("valid app version");
+        assert!(!other.supports_content_hash());
+    }
+
+    #[test]
+    fn parses_text_offer() {
+        let message: PeerMessage =
+            serde_json::from_str(r#"{"offer": {"message": "hello"}}"#).expect("valid message");
+        assert!(matches!(
+            message,
+            PeerMessage::Offer(OfferMessage::Message(text)) if text == "hello"
+        ));
+    }
+
+    #[test]
+    fn serializes_directory_offer() {
+        let message = PeerMessage::Offer(OfferMessage::Directory {
+            dirname: "folder".to_owned(),
+            mode: DIRECTORY_MODE.to_owned(),
+            zipsize: 45,
+            numbytes: 1234,
+            numfiles: 10,
+        });
+        assert_eq!(
+            serde_json::to_string(&message).expect("serializable message"),
+            r#"{"offer":{"directory":{"dirname":"folder","mode":"zipfile/deflated","zipsize":45,"numbytes":1234,"numfiles":10}}}"#
+        );
+        assert!(is_supported_directory_mode(DIRECTORY_MODE));
+        assert!(!is_supported_directory_mode("zipfile/bzip2"));
+        assert!(!is_supported_directory_mode("tarball"));
+    }
+
+    #[test]
+    fn serializes_resume_rejection() {
+        let message = PeerMessage::ResumeRejected("mismatch".to_owned());
+        assert_eq!(
+            serde_json::to_string(&message).expect("serializable message"),
+            r#"{"resume-rejected":"mismatch"}"#
+        );
+    }
+
+    #[test]
+    fn serializes_message_ack() {
+        let message = PeerMessage::Answer(AnswerMessage::MessageAck("ok".to_owned()));
+        assert_eq!(
+            serde_json::to_string(&message).expect("serializable message"),
+            r#"{"answer":{"message_ack":"ok"}}"#
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use super::{init_transit, Records, TransitConnector};
+use futures::channel::mpsc;
+use futures::{join, StreamExt};
+#[allow(deprecated)]
+use magic_wormhole::transit::TransitKey;
+use magic_wormhole::transit::{Abilities, DirectHint, Hints, RelayHint, Transit, TransitError};
+use magic_wormhole::Key;
+use std::io;
+use std::sync::Arc;
+
+/// A local stand-in for a transit connection, which breaks down after sending `records_left` records.
+pub(crate) struct LocalTransit {
+    sender: Option<mpsc::UnboundedSender<Box<[u8]>>>,
+    receiver: mpsc::UnboundedReceiver<Box<[u8]>>,
+    records_left: usize,
+}
+
+impl Records for LocalTransit {
+    async fn receive_record(&mut self) -> Result<Box<[u8]>, TransitError> {
+        self.receiver
+            .next()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::ConnectionReset).into())
+    }
+
+    async fn send_record(&mut self, record: &[u8]) -> Result<(), TransitError> {
+        if self.records_left == 0 {
+            self.sender = None;
+        }
+        self.records_left = self.records_left.saturating_sub(1);
+        match &self.sender {
+            Some(sender) => sender
+                .unbounded_send(record.into())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe).into()),
+            None => Err(io::Error::from(io::ErrorKind::ConnectionReset).into()),
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), TransitError> {
+        Ok(())
+    }
+}
+
+/// Connects a sender, which breaks down after `records_left` records, with a receiver.
+pub(crate) fn local_transit(records_left: usize) -> (LocalTransit, LocalTransit) {
+    let (to_receiver, from_sender) = mpsc::unbounded();
+    let (to_sender, from_receiver) = mpsc::unbounded();
+    let sender = LocalTransit {
+        sender: Some(to_receiver),
+        receiver: from_receiver,
+        records_left,
+    };
+    let receiver = LocalTransit {
+        sender: Some(to_sender),
+        receiver: from_sender,
+        records_left: usize::MAX,
+    };
+    (sender, receiver)
+}
+
+/// Connects a sender with a receiver over a real transit connection to 127.0.0.1,
+/// e.g. to measure the throughput including encryption and TCP.
+#[allow(deprecated)]
+pub(crate) async fn loopback_transit() -> (Transit, Transit) {
+    let leader = init_transit(Vec::new())
+        .await
+        .expect("transit to be initialized");
+    let follower = init_transit(Vec::new())
+        .await
+        .expect("transit to be initialized");
+    let leader_hints = loopback_hints(&leader);
+    let follower_hints = loopback_hints(&follower);
+
+    let (sender, receiver) = join!(
+        leader.leader_connect(
+            Key::<TransitKey>::new(Box::default()),
+            Abilities::ALL,
+            follower_hints
+        ),
+        follower.follower_connect(
+            Key::<TransitKey>::new(Box::default()),
+            Abilities::ALL,
+            leader_hints
+        ),
+    );
+    (
+        sender.expect("sender to connect").0,
+        receiver.expect("receiver to connect").0,
+    )
+}
+
+/// The ports that the connector listens on, but on 127.0.0.1 instead of the network interfaces.
+fn loopback_hints(connector: &TransitConnector) -> Arc<Hints> {
+    let direct_hints = connector
+        .our_hints()
+        .direct_tcp
+        .iter()
+        .map(|hint| DirectHint::new("127.0.0.1", hint.port));
+    Arc::new(Hints::new(direct_hints, Vec::<RelayHint>::new()))
+}
@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+/// Samples closer together than this are skipped, as they're too noisy to estimate a rate from.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How much a new sample contributes to the smoothed rate.
+const SMOOTHING_FACTOR: f64 = 0.3;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TransferRate {
+    /// `None` until enough progress has been made to estimate the rate.
+    pub bytes_per_second: Option<u64>,
+    pub elapsed: Duration,
+    pub remaining: Option<Duration>,
+}
+
+/// Estimates the transfer rate using an exponential moving average
+/// so that the displayed rate and ETA don't jump around.
+/// The elapsed time is measured from when the estimator is created,
+/// so it should be created once the first progress is reported.
+/// The rate is measured from the first reported progress, which isn't zero for resumed transfers.
+#[derive(Debug)]
+pub(crate) struct RateEstimator {
+    started: Instant,
+    last_sample: Option<(Instant, u64)>,
+    bytes_per_second: Option<f64>,
+}
+
+impl Default for RateEstimator {
+    fn default() -> Self {
+        RateEstimator::new(Instant::now())
+    }
+}
+
+impl RateEstimator {
+    fn new(started: Instant) -> Self {
+        RateEstimator {
+            started,
+            last_sample: None,
+            bytes_per_second: None,
+        }
+    }
+
+    pub(crate) fn update(&mut self, value: u64, total: u64) -> TransferRate {
+        self.update_at(Instant::now(), value, total)
+    }
+
+    fn update_at(&mut self, now: Instant, value: u64, total: u64) -> TransferRate {
+        let (last_time, last_value) = *self.last_sample.get_or_insert((self.started, value));
+        let interval = now.saturating_duration_since(last_time);
+        if interval >= SAMPLE_INTERVAL {
+            let sample = value.saturating_sub(last_value) as f64 / interval.as_secs_f64();
+            self.bytes_per_second = Some(match self.bytes_per_second {
+                Some(rate) => SMOOTHING_FACTOR * sample + (1.0 - SMOOTHING_FACTOR) * rate,
+                None => sample,
+            });
+            self.last_sample = Some((now, value));
+        }
+
+        let bytes_per_second = self.bytes_per_second.filter(|rate| *rate >= 1.0);
+        TransferRate {
+            bytes_per_second: bytes_per_second.map(|rate| rate as u64),
+            elapsed: now.saturating_duration_since(self.started),
+            remaining: bytes_per_second
+                .map(|rate| Duration::from_secs_f64(total.saturating_sub(value) as f64 / rate)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_rate_and_remaining_time() {
+        let started = Instant::now();
+        let mut estimator = RateEstimator::new(started);
+
+        estimator.update_at(started, 0, 10_000);
+        let rate = estimator.update_at(started + Duration::from_secs(1), 1000, 10_000);
+        assert_eq!(rate.bytes_per_second, Some(1000));
+        assert_eq!(rate.elapsed, Duration::from_secs(1));
+        assert_eq!(rate.remaining, Some(Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn smooths_rate_changes() {
+        let started = Instant::now();
+        let mut estimator = RateEstimator::new(started);
+
+        estimator.update_at(started, 0, 10_000);
+        estimator.update_at(started + Duration::from_secs(1), 1000, 10_000);
+        let rate = estimator.update_at(started + Duration::from_secs(2), 3000, 10_000);
+        assert_eq!(rate.bytes_per_second, Some(1300));
+    }
+
+    #[test]
+    fn skips_samples_that_are_too_close_together() {
+        let started = Instant::now();
+        let mut estimator = RateEstimator::new(started);
+
+        let rate = estimator.update_at(started + Duration::from_millis(10), 1000, 10_000);
+        assert_eq!(rate.bytes_per_second, None);
+        assert_eq!(rate.remaining, None);
+    }
+
+    #[test]
+    fn measures_resumed_transfer_from_first_progress() {
+        let started = Instant::now();
+        let mut estimator = RateEstimator::new(started);
+
+        estimator.update_at(started, 9000, 10_000);
+        let rate = estimator.update_at(started + Duration::from_secs(1), 9100, 10_000);
+        assert_eq!(rate.bytes_per_second, Some(100));
+        assert_eq!(rate.remaining, Some(Duration::from_secs(9)));
+    }
+}
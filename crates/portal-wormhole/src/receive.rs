@@ -1,33 +1,53 @@
 use crate::cancellation::{CancellationSource, CancellationToken};
 use crate::error::PortalError;
-use crate::fs::{mark_as_downloaded, open_with_conflict_resolution, sanitize_file_name};
-use crate::sync::BorrowingOneshotReceiver;
-use crate::transit::{
-    progress_handler, transit_handler, ProgressHandler, TransitHandler, RELAY_HINTS,
+use crate::extract::{extract_zip, is_extractable};
+use crate::fs::{
+    available_space, mark_as_downloaded, open_with_conflict_resolution, preallocate,
+    release_preallocated, rename_with_conflict_resolution, sanitize_file_name,
+};
+use crate::protocol::{
+    finish, init_transit, is_supported_directory_mode, receive_message, receive_records, reject,
+    send_message, transit_key, unexpected_message, until_canceled, verify_content_hash,
+    AnswerMessage, OfferMessage, PeerMessage, Prefix, TransitConnector, TransitMessage,
 };
-use crate::{Progress, RequestRepaint};
+use crate::resume::{ResumePaths, ResumeState};
+use crate::sync::BorrowingOneshotReceiver;
+use crate::transit::{progress_handler, transit_handler, ProgressHandler, TransitHandler};
+use crate::{ConnectionSettings, ContentHash, Progress, RequestRepaint, TransferRate, Verifier};
 use async_std::fs::File;
-use futures::channel::oneshot;
-use futures::future::Abortable;
-use futures::Future;
-use magic_wormhole::transfer::{self, ReceiveRequest};
-use magic_wormhole::transit::{Abilities, TransitInfo};
+use async_std::task::spawn_blocking;
+use futures::future::{select, Abortable, Either};
+use futures::io::BufWriter;
+use futures::{pin_mut, AsyncWriteExt, Future};
+use log::warn;
+use magic_wormhole::transfer::TransferError;
+use magic_wormhole::transit::{RelayHint, Transit, TransitInfo};
 use magic_wormhole::{Code, MailboxConnection, Wormhole};
 use single_value_channel as svc;
 use std::fs::{self, OpenOptions};
 use std::mem;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-pub type ConnectResult = Result<ReceiveRequestController, PortalError>;
+pub type ConnectResult = Result<ReceiveOffer, PortalError>;
 pub type ReceiveResult = Result<PathBuf, PortalError>;
 
-pub fn connect(code: Code) -> (impl Future<Output = ConnectResult>, ConnectingController) {
+/// Records are much smaller than this, so they are collected into fewer, larger writes.
+const WRITE_BUFFER_SIZE: usize = 1024 * 1024;
+
+pub fn connect(
+    code: Code,
+    connection_settings: ConnectionSettings,
+) -> (impl Future<Output = ConnectResult>, ConnectingController) {
     let cancellation_source = CancellationSource::default();
     let cancellation_token = cancellation_source.token();
     let controller = ConnectingController {
         cancellation_source,
     };
-    (connect_impl(code, cancellation_token), controller)
+    (
+        connect_impl(code, connection_settings, cancellation_token),
+        controller,
+    )
 }
 
 pub struct ConnectingController {
@@ -40,79 +60,403 @@ impl ConnectingController {
     }
 }
 
-async fn connect_impl(code: Code, cancellation: CancellationToken) -> ConnectResult {
+async fn connect_impl(
+    code: Code,
+    connection_settings: ConnectionSettings,
+    cancellation: CancellationToken,
+) -> ConnectResult {
     const ALLOCATE_NAMEPLATE_IF_MISSING: bool = false;
+    let relay_hints = connection_settings.relay_hints()?;
     let mailbox = Abortable::new(
-        MailboxConnection::connect(transfer::APP_CONFIG, code, ALLOCATE_NAMEPLATE_IF_MISSING),
+        MailboxConnection::connect(
+            connection_settings.app_config(),
+            code,
+            ALLOCATE_NAMEPLATE_IF_MISSING,
+        ),
         cancellation.as_abort_registration(),
     )
     .await??;
-    let wormhole = Abortable::new(
+    let mut wormhole = Abortable::new(
         Wormhole::connect(mailbox),
         cancellation.as_abort_registration(),
     )
     .await??;
 
-    transfer::request_file(
-        wormhole,
-        RELAY_HINTS.clone(),
-        Abilities::ALL,
+    let offer = until_canceled(
+        receive_offer(&mut wormhole, relay_hints),
         cancellation.as_future(),
     )
-    .await?
-    .ok_or(PortalError::Canceled)
-    .map(|receive_request| ReceiveRequestController { receive_request })
+    .await;
+
+    match offer {
+        Ok(IncomingOffer::Text(text)) => Ok(ReceiveOffer::Text(ReceiveTextController {
+            verifier: Verifier::new(wormhole.verifier()),
+            wormhole,
+            text,
+        })),
+        Ok(IncomingOffer::File {
+            file_name,
+            file_size,
+            is_directory,
+            resumable,
+            connector,
+            their_transit,
+        }) => Ok(ReceiveOffer::File(ReceiveRequestController {
+            verifier: Verifier::new(wormhole.verifier()),
+            wormhole,
+            connector,
+            their_transit,
+            file_name,
+            file_size,
+            is_directory,
+            resumable,
+        })),
+        Err(error) => finish(wormhole, Err(error)).await,
+    }
+}
+
+enum IncomingOffer {
+    Text(String),
+    File {
+        file_name: String,
+        file_size: u64,
+        is_directory: bool,
+        resumable: bool,
+        connector: TransitConnector,
+        their_transit: TransitMessage,
+    },
+}
+
+/// Waits for the offer and the transit hints of the sender, which may arrive in any order.
+/// Text messages are returned right away, as they are not sent over the transit connection.
+async fn receive_offer(
+    wormhole: &mut Wormhole,
+    relay_hints: Vec<RelayHint>,
+) -> Result<IncomingOffer, PortalError> {
+    let connector = init_transit(relay_hints).await?;
+    send_message(wormhole, &PeerMessage::transit(&connector)).await?;
+
+    let mut their_transit = None;
+    let mut file_offer = None;
+    loop {
+        match receive_message(wormhole).await? {
+            PeerMessage::Transit(transit) => their_transit = Some(transit),
+            PeerMessage::Offer(OfferMessage::Message(text)) => {
+                return Ok(IncomingOffer::Text(text))
+            }
+            PeerMessage::Offer(OfferMessage::File {
+                filename,
+                filesize,
+                resumable,
+            }) => file_offer = Some((filename, filesize, false, resumable)),
+            PeerMessage::Offer(OfferMessage::Directory {
+                dirname,
+                mode,
+                zipsize,
+                ..
+            }) if is_supported_directory_mode(&mode) => {
+                file_offer = Some((dirname, zipsize, true, false))
+            }
+            PeerMessage::Offer(OfferMessage::Directory { .. } | OfferMessage::Unknown) => {
+                return Err(TransferError::UnsupportedOffer.into())
+            }
+            message => return Err(unexpected_message("offer", &message)),
+        }
+
+        if let (Some(_), Some(_)) = (&file_offer, &their_transit) {
+            let (file_name, file_size, is_directory, resumable) =
+                file_offer.expect("checked above");
+            return Ok(IncomingOffer::File {
+                file_name,
+                file_size,
+                is_directory,
+                resumable,
+                connector,
+                their_transit: their_transit.expect("checked above"),
+            });
+        }
+    }
+}
+
+/// What the peer wants to send us.
+pub enum ReceiveOffer {
+    /// A text message, which has already been received in full,
+    /// but is only shown once it has been accepted.
+    Text(ReceiveTextController),
+    File(ReceiveRequestController),
+}
+
+pub struct ReceiveTextController {
+    wormhole: Wormhole,
+    text: String,
+    verifier: Verifier,
+}
+
+impl ReceiveTextController {
+    /// Compare this with the verifier shown to the sender to make sure the message comes from them.
+    pub fn verifier(&self) -> &Verifier {
+        &self.verifier
+    }
+
+    /// Acknowledges the message, which completes the transfer for the sender, and returns it.
+    pub async fn accept(self) -> Result<String, PortalError> {
+        let ReceiveTextController {
+            mut wormhole, text, ..
+        } = self;
+        let ack = PeerMessage::Answer(AnswerMessage::MessageAck("ok".to_owned()));
+        let result = send_message(&mut wormhole, &ack).await.map(|()| text);
+        finish(wormhole, result).await
+    }
+
+    pub async fn reject(self) -> Result<(), PortalError> {
+        reject(self.wormhole).await
+    }
 }
 
 pub struct ReceiveRequestController {
-    receive_request: ReceiveRequest,
+    wormhole: Wormhole,
+    connector: TransitConnector,
+    their_transit: TransitMessage,
+    file_name: String,
+    file_size: u64,
+    is_directory: bool,
+    /// Whether the sender can resume the transfer of a file that we have received partially.
+    resumable: bool,
+    verifier: Verifier,
 }
 
 impl ReceiveRequestController {
+    /// The name of the offered file or directory.
+    /// This is untrusted input and must be sanitized before it's used as a path.
     pub fn file_name(&self) -> String {
-        self.receive_request.file_name()
+        self.file_name.clone()
     }
 
+    /// The name of the offered file with characters that are not allowed in file names replaced.
+    pub fn sanitized_file_name(&self) -> String {
+        sanitize_file_name(&self.file_name, "_").into_owned()
+    }
+
+    /// The size of the offered file. For directories, this is the size of the Zip file
+    /// in which they are transferred.
     pub fn filesize(&self) -> u64 {
-        self.receive_request.file_size()
+        self.file_size
+    }
+
+    /// Directories are transferred as a Zip file, which is unpacked after receiving it.
+    pub fn is_directory(&self) -> bool {
+        self.is_directory
+    }
+
+    /// Compare this with the verifier shown to the sender to make sure the offer comes from them.
+    pub fn verifier(&self) -> &Verifier {
+        &self.verifier
+    }
+
+    /// How much of the file has already been received to the target in an interrupted transfer,
+    /// if the transfer can be resumed with [`ReceiveOptions::resume`].
+    pub fn resumable(&self, options: &ReceiveOptions) -> Option<Progress> {
+        let paths = self.resume_paths(&options.target)?;
+        paths
+            .load()
+            .filter(|state| state.matches(&self.file_name, self.file_size, &paths.part))
+            .map(|state| Progress {
+                value: state.received,
+                total: state.file_size,
+            })
+    }
+
+    /// Where the state of an interrupted transfer is kept, unless the offer can't be resumed.
+    fn resume_paths(&self, target: &ReceiveTarget) -> Option<ResumePaths> {
+        if !self.resumable || self.is_directory {
+            return None;
+        }
+        let destination = target.destination(&self.file_name, false).ok()?;
+        ResumePaths::new(&destination.path, destination.part_path())
+    }
+
+    /// How much space is needed on the disk of the target and how much of it is available,
+    /// unless the available space can't be determined.
+    /// Extracting a folder needs additional space, which isn't known before receiving it.
+    pub fn disk_space(&self, options: &ReceiveOptions) -> Option<DiskSpace> {
+        let destination = options
+            .target
+            .destination(&self.file_name, self.is_directory)
+            .ok()?;
+        let directory = destination
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        // A partially received file is either continued or removed before receiving.
+        let received = self.resumable(options).map_or(0, |progress| progress.value);
+        Some(DiskSpace {
+            required: self.file_size.saturating_sub(received),
+            available: available_space(directory)?,
+        })
     }
 
     pub fn accept(
         self,
+        options: ReceiveOptions,
         request_repaint: impl RequestRepaint,
     ) -> (impl Future<Output = ReceiveResult>, ReceivingController) {
-        ReceivingController::new(self.receive_request, request_repaint)
+        ReceivingController::new(self, options, request_repaint)
     }
 
     pub async fn reject(self) -> Result<(), PortalError> {
-        Ok(self.receive_request.reject().await?)
+        reject(self.wormhole).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    /// The number of bytes that are written to the disk while receiving.
+    pub required: u64,
+    pub available: u64,
+}
+
+impl DiskSpace {
+    pub fn is_sufficient(&self) -> bool {
+        self.available >= self.required
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReceiveOptions {
+    pub target: ReceiveTarget,
+    /// Extract received folders and Zip archives into a folder next to the archive.
+    /// The archive is removed after it has been extracted.
+    pub extract_archives: bool,
+    /// Continue an interrupted transfer of the same file instead of starting over,
+    /// see [`ReceiveRequestController::resumable`].
+    pub resume: bool,
+}
+
+/// Where a received file is saved.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ReceiveTarget {
+    /// The user's Downloads folder.
+    #[default]
+    Downloads,
+    /// A folder in which the file is saved under the name chosen by the peer.
+    Directory(PathBuf),
+    /// The exact path of the file, which is overwritten if it exists.
+    /// Directories are unpacked to this path.
+    File(PathBuf),
+}
+
+impl ReceiveTarget {
+    /// Where the offered file or directory ends up once it has been received completely.
+    fn destination(
+        &self,
+        untrusted_file_name: &str,
+        is_directory: bool,
+    ) -> Result<Destination, PortalError> {
+        let (directory, file_name) = match self {
+            ReceiveTarget::File(path) if !is_directory => {
+                return Ok(Destination {
+                    path: path.clone(),
+                    replace: true,
+                })
+            }
+            ReceiveTarget::File(path) => (
+                path.parent().unwrap_or(Path::new(".")).to_owned(),
+                path.file_name().unwrap_or_default().to_string_lossy(),
+            ),
+            ReceiveTarget::Downloads => (
+                dirs::download_dir().ok_or(PortalError::MissingDownloadsDirectory)?,
+                untrusted_file_name.into(),
+            ),
+            ReceiveTarget::Directory(directory) => (directory.clone(), untrusted_file_name.into()),
+        };
+        // Directories are received as a Zip file next to the folder they are unpacked to.
+        let file_name = if is_directory {
+            format!("{file_name}.zip")
+        } else {
+            file_name.into_owned()
+        };
+        Ok(Destination {
+            path: directory.join(sanitize_file_name(file_name, "_").as_ref()),
+            replace: false,
+        })
+    }
+}
+
+/// Files are received to a `.part` file next to their destination and only moved there once
+/// they are complete, so that the destination never contains a truncated file.
+struct Destination {
+    path: PathBuf,
+    /// Whether an existing file is replaced, otherwise the file is saved under a different name.
+    replace: bool,
+}
+
+impl Destination {
+    fn part_path(&self) -> PathBuf {
+        let mut part_path = self.path.clone().into_os_string();
+        part_path.push(".part");
+        part_path.into()
+    }
+
+    fn create_part_file(&self) -> Result<(fs::File, PathBuf), PortalError> {
+        Ok(open_with_conflict_resolution(&self.part_path(), |path| {
+            OpenOptions::new()
+                .create_new(true)
+                .write(true)
+                .open(path)
+                .map(|f| (f, path.to_owned()))
+        })?)
+    }
+
+    /// Moves the complete file to its destination and returns where it has been saved.
+    fn persist(&self, part_path: &Path) -> Result<PathBuf, PortalError> {
+        if self.replace {
+            fs::rename(part_path, &self.path)?;
+            Ok(self.path.clone())
+        } else {
+            Ok(rename_with_conflict_resolution(part_path, &self.path)?)
+        }
     }
 }
 
 pub struct ReceivingController {
     transit_info_receiver: BorrowingOneshotReceiver<TransitInfo>,
     progress: svc::Receiver<Progress>,
-    cancel_sender: Option<oneshot::Sender<()>>,
+    rate: svc::Receiver<TransferRate>,
+    extraction_progress: svc::Receiver<Option<Progress>>,
+    content_hash_receiver: BorrowingOneshotReceiver<ContentHash>,
+    cancellation_source: CancellationSource,
 }
 
 impl ReceivingController {
     fn new(
-        receive_request: ReceiveRequest,
+        receive_request: ReceiveRequestController,
+        options: ReceiveOptions,
         request_repaint: impl RequestRepaint,
     ) -> (impl Future<Output = ReceiveResult>, Self) {
         let (transit_info_sender, transit_info_receiver) = ::oneshot::channel();
         let (progress, progress_updater) = svc::channel_starting_with(Progress::default());
-        let (cancel_sender, cancel_receiver) = oneshot::channel();
+        let (rate, rate_updater) = svc::channel_starting_with(TransferRate::default());
+        let (extraction_progress, extraction_progress_updater) = svc::channel();
+        let (content_hash_sender, content_hash_receiver) = ::oneshot::channel();
+        let cancellation_source = CancellationSource::default();
+        let cancellation_token = cancellation_source.token();
         let controller = ReceivingController {
             transit_info_receiver: transit_info_receiver.into(),
             progress,
-            cancel_sender: Some(cancel_sender),
+            rate,
+            extraction_progress,
+            content_hash_receiver: content_hash_receiver.into(),
+            cancellation_source,
         };
         let future = accept(
             receive_request,
+            options,
             transit_handler(transit_info_sender, request_repaint.clone()),
-            progress_handler(progress_updater, request_repaint),
-            cancel_receiver,
+            progress_handler(progress_updater, rate_updater, request_repaint.clone()),
+            extraction_progress_handler(extraction_progress_updater, request_repaint),
+            content_hash_sender,
+            cancellation_token,
         );
         (future, controller)
     }
@@ -125,47 +469,362 @@ impl ReceivingController {
         self.progress.latest()
     }
 
+    pub fn rate(&mut self) -> &TransferRate {
+        self.rate.latest()
+    }
+
+    /// The progress of extracting the received archive, once extraction has started.
+    pub fn extraction_progress(&mut self) -> Option<&Progress> {
+        self.extraction_progress.latest().as_ref()
+    }
+
+    /// The digest of the received file, once it has been received completely.
+    pub fn content_hash(&mut self) -> Option<&ContentHash> {
+        self.content_hash_receiver.value()
+    }
+
     pub fn cancel(&mut self) {
-        self.cancel_sender.take().map(|c| c.send(()));
+        self.cancellation_source.cancel()
+    }
+}
+
+fn extraction_progress_handler(
+    updater: svc::Updater<Option<Progress>>,
+    mut request_repaint: impl RequestRepaint,
+) -> impl FnMut(u64, u64) + Send + 'static {
+    move |value, total| {
+        _ = updater.update(Some(Progress { value, total }));
+        request_repaint();
     }
 }
 
 async fn accept(
-    receive_request: ReceiveRequest,
+    receive_request: ReceiveRequestController,
+    options: ReceiveOptions,
     transit_handler: impl TransitHandler,
     progress_handler: impl ProgressHandler,
-    cancel: oneshot::Receiver<()>,
+    extraction_progress_handler: impl FnMut(u64, u64) + Send + 'static,
+    content_hash_sender: ::oneshot::Sender<ContentHash>,
+    cancellation: CancellationToken,
 ) -> ReceiveResult {
-    let untrusted_filename = receive_request.file_name();
-    let base_path = {
-        let mut path = dirs::download_dir().expect("Unable to detect downloads directory");
-        path.push(sanitize_file_name(untrusted_filename, "_").as_ref());
-        path
+    let resume_paths = receive_request.resume_paths(&options.target);
+    let saved_state = resume_paths.as_ref().and_then(ResumePaths::load);
+    let disk_space = receive_request.disk_space(&options);
+    let destination = options
+        .target
+        .destination(&receive_request.file_name, receive_request.is_directory);
+    let ReceiveRequestController {
+        mut wormhole,
+        connector,
+        their_transit,
+        file_name: untrusted_filename,
+        file_size,
+        is_directory,
+        resumable: _,
+        verifier: _,
+    } = receive_request;
+
+    let destination = match destination {
+        Ok(destination) => destination,
+        Err(error) => return finish(wormhole, Err(error)).await,
     };
-    let (file, file_path) = open_with_conflict_resolution(&base_path, |path| {
-        OpenOptions::new()
-            .create_new(true)
-            .write(true)
-            .open(path)
-            .map(|f| (f, path.to_owned()))
-    })?;
-    let mut async_file = File::from(file);
-
-    let mut canceled = false;
-    receive_request
-        .accept(transit_handler, progress_handler, &mut async_file, async {
-            _ = cancel.await;
-            canceled = true;
-        })
-        .await?;
 
-    if canceled {
-        mem::drop(async_file);
-        fs::remove_file(file_path)?;
-        return Err(PortalError::Canceled);
+    // Otherwise, the transfer would only fail once the disk is full.
+    if let Some(DiskSpace {
+        required,
+        available,
+    }) = disk_space.filter(|disk_space| !disk_space.is_sufficient())
+    {
+        let error = PortalError::InsufficientSpace {
+            required,
+            available,
+        };
+        return finish(wormhole, Err(error)).await;
     }
 
+    // A partially received file that isn't resumed is outdated.
+    let resumed = match (saved_state, &resume_paths) {
+        (Some(state), Some(paths))
+            if options.resume && state.matches(&untrusted_filename, file_size, &paths.part) =>
+        {
+            match state.open_partial(&paths.part).await {
+                Ok((file, prefix)) => Some((file, paths.part.clone(), prefix)),
+                Err(PortalError::ResumeMismatch) => {
+                    paths.discard();
+                    None
+                }
+                Err(error) => return finish(wormhole, Err(error)).await,
+            }
+        }
+        (Some(_), Some(paths)) => {
+            paths.discard();
+            None
+        }
+        _ => None,
+    };
+    let (async_file, part_path, mut prefix) = match resumed {
+        Some(resumed) => resumed,
+        None => match destination.create_part_file() {
+            Ok((file, part_path)) => (File::from(file), part_path, Prefix::default()),
+            Err(error) => return finish(wormhole, Err(error)).await,
+        },
+    };
+    let mut writer = part_file_writer(async_file, file_size);
+
+    let result = until_canceled(
+        async {
+            let mut transit = accept_offer(
+                &mut wormhole,
+                connector,
+                their_transit,
+                &prefix,
+                transit_handler,
+            )
+            .await?;
+            receive_records(
+                &mut transit,
+                file_size,
+                &mut prefix,
+                progress_handler,
+                &mut writer,
+            )
+            .await?;
+            verify_content_hash(&mut wormhole, prefix.sha256()).await
+        },
+        cancellation.as_future(),
+    )
+    .await;
+
+    // The part file has to contain everything that has been received before its state is saved,
+    // the space that has been reserved for the rest isn't needed until the transfer is resumed.
+    if result.is_err() && writer.flush().await.is_ok() {
+        if let Err(error) = release_preallocated(writer.get_ref(), prefix.size) {
+            warn!("failed to release the space reserved for the received file: {error}");
+        }
+    }
+    mem::drop(writer);
+
+    match (&result, resume_paths) {
+        (
+            Err(
+                PortalError::Canceled
+                | PortalError::ResumeMismatch
+                | PortalError::ContentHashMismatch,
+            ),
+            resume_paths,
+        ) => {
+            if let Err(error) = fs::remove_file(&part_path) {
+                warn!("failed to remove the partially received file: {error}");
+            }
+            if let Some(resume_paths) = resume_paths {
+                resume_paths.forget();
+            }
+        }
+        // The part file is kept, so that the transfer can be resumed.
+        // A part file with a different name (because the expected one was taken) can't be found again.
+        (Err(_), Some(resume_paths)) if prefix.size > 0 && part_path == resume_paths.part => {
+            let state = ResumeState {
+                file_name: untrusted_filename,
+                file_size,
+                received: prefix.size,
+                sha256: prefix.sha256(),
+            };
+            if let Err(error) = resume_paths.save(&state) {
+                warn!("failed to save the state of the interrupted transfer: {error}");
+            }
+        }
+        (Err(_), _) => _ = fs::remove_file(&part_path),
+        (Ok(_), Some(resume_paths)) => resume_paths.forget(),
+        (Ok(_), None) => {}
+    }
+    let result = result.and_then(|content_hash| {
+        _ = content_hash_sender.send(content_hash);
+        destination.persist(&part_path)
+    });
+    let file_path = finish(wormhole, result).await?;
+
     mark_as_downloaded(&file_path);
 
-    Ok(file_path)
+    // Older peers and other clients send folders as plain Zip files.
+    if options.extract_archives && (is_directory || is_extractable(&file_path)) {
+        spawn_blocking(move || extract_zip(&file_path, extraction_progress_handler, cancellation))
+            .await
+    } else {
+        Ok(file_path)
+    }
+}
+
+/// Reserves the space for the whole file, so that large files are not fragmented
+/// while they are written in small records.
+fn part_file_writer(file: File, file_size: u64) -> BufWriter<File> {
+    if let Err(error) = preallocate(&file, file_size) {
+        warn!("failed to preallocate the received file: {error}");
+    }
+    BufWriter::with_capacity(WRITE_BUFFER_SIZE, file)
+}
+
+/// Answers the offer, asking the sender to skip the prefix that we already have,
+/// and connects to the sender.
+async fn accept_offer(
+    wormhole: &mut Wormhole,
+    connector: TransitConnector,
+    their_transit: TransitMessage,
+    prefix: &Prefix,
+    transit_handler: impl TransitHandler,
+) -> Result<Transit, PortalError> {
+    let answer = if prefix.size > 0 {
+        AnswerMessage::FileResume {
+            offset: prefix.size,
+            sha256: prefix.sha256(),
+        }
+    } else {
+        AnswerMessage::FileAck("ok".to_owned())
+    };
+    send_message(wormhole, &PeerMessage::Answer(answer)).await?;
+
+    let transit_key = transit_key(wormhole);
+    let connect = connector.follower_connect(
+        transit_key,
+        their_transit.abilities_v1,
+        Arc::new(their_transit.hints_v1),
+    );
+    // The sender reports errors, such as a mismatching prefix, over the wormhole instead of connecting.
+    let peer_error = async {
+        match receive_message(wormhole).await {
+            Ok(message) => unexpected_message("transit connection", &message),
+            Err(error) => error,
+        }
+    };
+    pin_mut!(connect);
+    pin_mut!(peer_error);
+    let (transit, transit_info) = match select(connect, peer_error).await {
+        Either::Left((result, _)) => result?,
+        Either::Right((error, _)) => return Err(error),
+    };
+    transit_handler(transit_info);
+    Ok(transit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::local_transit::{local_transit, loopback_transit};
+    use crate::protocol::{send_records, Records};
+    use async_std::task::block_on;
+    use futures::io::{repeat, AsyncReadExt, AsyncWrite};
+    use futures::join;
+    use std::io::Write;
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    /// Sends `file_size` bytes over the transit to a file that is written with `writer`
+    /// and returns the throughput in MiB/s.
+    fn receive_to_file<W: AsyncWrite + Unpin>(
+        (mut sending, mut receiving): (impl Records, impl Records),
+        path: &Path,
+        file_size: u64,
+        writer: impl FnOnce(File) -> W,
+    ) -> f64 {
+        let file = fs::File::create(path).expect("file to be created");
+        let mut writer = writer(File::from(file));
+        let mut reader = repeat(0x5a).take(file_size);
+        let mut prefix = Prefix::default();
+
+        let start = Instant::now();
+        let (sent, received) = block_on(async {
+            join!(
+                send_records(
+                    &mut sending,
+                    file_size,
+                    Prefix::default(),
+                    |_, _| {},
+                    &mut reader,
+                ),
+                receive_records(
+                    &mut receiving,
+                    file_size,
+                    &mut prefix,
+                    |_, _| {},
+                    &mut writer,
+                ),
+            )
+        });
+        sent.expect("sending to succeed");
+        received.expect("receiving to succeed");
+        file_size as f64 / start.elapsed().as_secs_f64() / (1024.0 * 1024.0)
+    }
+
+    #[test]
+    fn preallocated_part_file_has_received_size() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let path = temp_dir.path().join("file.bin");
+        receive_to_file(local_transit(usize::MAX), &path, 100_000, |file| {
+            part_file_writer(file, 100_000)
+        });
+        assert_eq!(
+            fs::read(&path).expect("file to be read"),
+            vec![0x5a; 100_000]
+        );
+    }
+
+    /// Compares receiving to the part file with writing every record to a plain file,
+    /// both over a transit connection to 127.0.0.1.
+    /// Run with `cargo test --release -p portal-wormhole receive_throughput -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn receive_throughput() {
+        const FILE_SIZE: u64 = 512 * 1024 * 1024;
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+
+        let unbuffered = receive_to_file(
+            block_on(loopback_transit()),
+            &temp_dir.path().join("unbuffered.bin"),
+            FILE_SIZE,
+            |file| file,
+        );
+        let buffered = receive_to_file(
+            block_on(loopback_transit()),
+            &temp_dir.path().join("buffered.bin"),
+            FILE_SIZE,
+            |file| part_file_writer(file, FILE_SIZE),
+        );
+        println!(
+            "unbuffered: {unbuffered:.0} MiB/s, preallocated and buffered: {buffered:.0} MiB/s"
+        );
+    }
+
+    #[test]
+    fn receives_to_part_file_next_to_destination() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        fs::write(temp_dir.path().join("file.txt"), "existing").expect("file to be written");
+        let target = ReceiveTarget::Directory(temp_dir.path().to_owned());
+        let destination = target
+            .destination("file.txt", false)
+            .expect("destination to be valid");
+
+        let (mut file, part_path) = destination
+            .create_part_file()
+            .expect("part file to be created");
+        assert_eq!(part_path, temp_dir.path().join("file.txt.part"));
+        file.write_all(b"received").expect("file to be written");
+        mem::drop(file);
+
+        let path = destination.persist(&part_path).expect("file to be moved");
+        assert_eq!(path, temp_dir.path().join("file (1).txt"));
+        assert_eq!(
+            fs::read_to_string(path).expect("file to be read"),
+            "received"
+        );
+        assert!(!part_path.exists());
+    }
+
+    #[test]
+    fn receives_directory_as_archive_next_to_chosen_path() {
+        let target = ReceiveTarget::File(PathBuf::from("/downloads/photos"));
+        let destination = target
+            .destination("../ignored", true)
+            .expect("destination to be valid");
+        assert_eq!(destination.path, Path::new("/downloads/photos.zip"));
+        assert!(!destination.replace);
+    }
 }
@@ -0,0 +1,201 @@
+//! Partially received files are kept as `.part` files next to their destination after an
+//! interrupted transfer, so that the transfer can be resumed when the same offer arrives again.
+//!
+//! What has been received is kept in the app's data directory, keyed by the destination,
+//! instead of next to the file: the download folder is written to by peers, so nothing
+//! in it can be trusted to describe a transfer.
+
+use crate::error::PortalError;
+use crate::protocol::Prefix;
+use async_std::fs::{File, OpenOptions};
+use futures::AsyncSeekExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ResumeState {
+    /// The name of the file as offered by the sender.
+    pub(crate) file_name: String,
+    pub(crate) file_size: u64,
+    /// The number of bytes that have been written to the partially received file.
+    pub(crate) received: u64,
+    /// The checksum of the received bytes.
+    pub(crate) sha256: String,
+}
+
+impl ResumeState {
+    /// Whether the state belongs to the offer and the partially received file still has the received bytes.
+    pub(crate) fn matches(&self, file_name: &str, file_size: u64, part_path: &Path) -> bool {
+        self.file_name == file_name
+            && self.file_size == file_size
+            && self.received > 0
+            && self.received < file_size
+            && fs::symlink_metadata(part_path)
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() >= self.received)
+    }
+
+    /// Opens the partially received file for writing after the received bytes.
+    /// The received bytes are checked before the file is changed,
+    /// anything written after the state has been saved is cut off.
+    pub(crate) async fn open_partial(
+        &self,
+        part_path: &Path,
+    ) -> Result<(File, Prefix), PortalError> {
+        let mut file = File::open(part_path).await?;
+        let prefix = match Prefix::read(&mut file, self.received).await {
+            Ok(prefix) if prefix.sha256() == self.sha256 => prefix,
+            Ok(_) => return Err(PortalError::ResumeMismatch),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(PortalError::ResumeMismatch)
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut file = OpenOptions::new().write(true).open(part_path).await?;
+        file.set_len(self.received).await?;
+        file.seek(SeekFrom::Start(self.received)).await?;
+        Ok((file, prefix))
+    }
+}
+
+/// Where the state of an interrupted transfer to a destination and the partially received file are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ResumePaths {
+    pub(crate) state: PathBuf,
+    pub(crate) part: PathBuf,
+}
+
+impl ResumePaths {
+    /// The state of a file that is received to `destination` is kept in the app's data directory
+    /// under the checksum of the destination, e.g. `~/.local/share/portal/resume/3a7b….json`.
+    pub(crate) fn new(destination: &Path, part: PathBuf) -> Option<Self> {
+        let directory = dirs::data_local_dir()?.join("portal").join("resume");
+        Some(ResumePaths {
+            state: state_path(&directory, destination),
+            part,
+        })
+    }
+
+    pub(crate) fn load(&self) -> Option<ResumeState> {
+        let json = fs::read(&self.state).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    pub(crate) fn save(&self, state: &ResumeState) -> Result<(), PortalError> {
+        if let Some(directory) = self.state.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(&self.state, serde_json::to_vec(state)?)?;
+        Ok(())
+    }
+
+    /// Removes the state, the partially received file is kept.
+    pub(crate) fn forget(&self) {
+        _ = fs::remove_file(&self.state);
+    }
+
+    /// Removes the state and the partially received file.
+    pub(crate) fn discard(&self) {
+        _ = fs::remove_file(&self.part);
+        self.forget();
+    }
+}
+
+fn state_path(directory: &Path, destination: &Path) -> PathBuf {
+    let destination = std::path::absolute(destination).unwrap_or_else(|_| destination.to_owned());
+    let key = Sha256::digest(destination.as_os_str().as_encoded_bytes());
+    directory.join(format!("{}.json", hex::encode(key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+    use tempfile::TempDir;
+
+    fn state(content: &[u8], received: u64) -> ResumeState {
+        let prefix =
+            block_on(Prefix::read(&mut Cursor::new(content), received)).expect("prefix to be read");
+        ResumeState {
+            file_name: "file.bin".to_owned(),
+            file_size: 1000,
+            received,
+            sha256: prefix.sha256(),
+        }
+    }
+
+    fn paths(temp_dir: &TempDir) -> ResumePaths {
+        let destination = temp_dir.path().join("downloads/file.bin");
+        ResumePaths {
+            state: state_path(&temp_dir.path().join("state"), &destination),
+            part: temp_dir.path().join("downloads/file.bin.part"),
+        }
+    }
+
+    #[test]
+    fn cuts_off_bytes_written_after_saving() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let paths = paths(&temp_dir);
+        fs::create_dir(temp_dir.path().join("downloads")).expect("dir to be created");
+        fs::write(&paths.part, b"received and unsaved").expect("file to be written");
+        let state = state(b"received", 8);
+
+        paths.save(&state).expect("state to be saved");
+        let loaded = paths.load().expect("state to be loaded");
+        assert_eq!(loaded, state);
+        assert!(loaded.matches("file.bin", 1000, &paths.part));
+        assert!(!loaded.matches("file.bin", 999, &paths.part));
+
+        let (_, prefix) =
+            block_on(loaded.open_partial(&paths.part)).expect("partial file to be opened");
+        assert_eq!(prefix.size, 8);
+        assert_eq!(fs::read(&paths.part).expect("file to be read"), b"received");
+    }
+
+    #[test]
+    fn leaves_changed_partial_file_untouched() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let path = temp_dir.path().join("file.bin.part");
+        fs::write(&path, b"changed and longer").expect("file to be written");
+        let state = state(b"received", 7);
+
+        let result = block_on(state.open_partial(&path));
+        assert!(matches!(result, Err(PortalError::ResumeMismatch)));
+        assert_eq!(
+            fs::read(&path).expect("file to be read"),
+            b"changed and longer"
+        );
+    }
+
+    #[test]
+    fn keeps_state_outside_of_destination_directory() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let state_directory = temp_dir.path().join("state");
+        let destination = temp_dir.path().join("downloads/file.bin");
+        let state_path = state_path(&state_directory, &destination);
+        assert_eq!(state_path.parent(), Some(state_directory.as_path()));
+        assert_ne!(
+            state_path,
+            super::state_path(
+                &state_directory,
+                &temp_dir.path().join("downloads/other.bin")
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ignores_symlinked_partial_file() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let target = temp_dir.path().join("target");
+        fs::write(&target, b"received").expect("file to be written");
+        let part = temp_dir.path().join("file.bin.part");
+        std::os::unix::fs::symlink(&target, &part).expect("symlink to be created");
+
+        assert!(!state(b"received", 8).matches("file.bin", 1000, &part));
+    }
+}
@@ -1,15 +1,26 @@
 use self::sendable_file::SendableFile;
 use crate::cancellation::{CancellationSource, CancellationToken};
 use crate::error::PortalError;
-use crate::transit::{ProgressHandler, TransitHandler, RELAY_HINTS};
-use crate::{Progress, RequestRepaint};
+use crate::protocol::{
+    finish, init_transit, receive_message, resumed_prefix, send_content_hash, send_message,
+    send_records, transit_key, unexpected_message, until_canceled, AnswerMessage, OfferMessage,
+    PeerMessage, Prefix, TransitMessage, DIRECTORY_MODE,
+};
+use crate::rate::RateEstimator;
+use crate::sync::BorrowingOneshotReceiver;
+use crate::transit::{ProgressHandler, TransitHandler};
+use crate::zip_stream::StreamingZip;
+use crate::{ConnectionSettings, ContentHash, Progress, RequestRepaint, TransferRate, Verifier};
 use async_std::fs::File;
+use futures::channel::oneshot;
 use futures::future::{Abortable, BoxFuture};
-use futures::Future;
+use futures::{AsyncRead, Future};
 use log::warn;
-use magic_wormhole::transit::{Abilities, TransitInfo};
-use magic_wormhole::{transfer, Code, MailboxConnection, Wormhole};
+use magic_wormhole::transfer::TransferError;
+use magic_wormhole::transit::{RelayHint, TransitInfo};
+use magic_wormhole::{Code, MailboxConnection, Wormhole};
 use single_value_channel as svc;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use trait_set::trait_set;
 
@@ -17,8 +28,39 @@ mod request;
 pub use self::request::{CachedSendRequest, SendRequest};
 mod sendable_file;
 
+/// Number of words in generated codes unless configured otherwise.
+pub const DEFAULT_WORD_COUNT: usize = 4;
+
+#[derive(Debug, Clone, Default)]
+pub struct SendOptions {
+    /// Waits for [`SendingController::confirm_verification`] before anything is sent to the peer.
+    pub require_verification: bool,
+    pub code: SendCode,
+}
+
+/// The code that the receiver uses to connect with us.
+#[derive(Debug, Clone)]
+pub enum SendCode {
+    /// A new code with the given number of words.
+    Generated { word_count: usize },
+    /// A code agreed on beforehand, its nameplate is allocated if nobody uses it yet.
+    Custom(Code),
+    /// The code of a peer that waits for us to send something.
+    Peer(Code),
+}
+
+impl Default for SendCode {
+    fn default() -> Self {
+        SendCode::Generated {
+            word_count: DEFAULT_WORD_COUNT,
+        }
+    }
+}
+
 pub fn send(
     send_request: SendRequest,
+    connection_settings: ConnectionSettings,
+    options: SendOptions,
     request_repaint: impl RequestRepaint,
 ) -> (
     impl Future<Output = Result<(), (PortalError, SendRequest)>>,
@@ -26,17 +68,32 @@ pub fn send(
 ) {
     let (progress_receiver, progress_updater) =
         svc::channel_starting_with(SendingProgress::Connecting);
+    let (verification_sender, verification) = if options.require_verification {
+        let (sender, receiver) = oneshot::channel();
+        (Some(sender), Some(receiver))
+    } else {
+        (None, None)
+    };
 
+    let (content_hash_sender, content_hash_receiver) = ::oneshot::channel();
     let cancellation_source = CancellationSource::default();
     let cancellation_token = cancellation_source.token();
     let controller = SendingController {
         progress_receiver,
+        verification_sender,
+        content_hash_receiver: content_hash_receiver.into(),
         cancellation_source,
     };
 
     let future = send_impl(
         send_request,
+        connection_settings,
+        PeerConnection {
+            code: options.code,
+            verification,
+        },
         report(progress_updater, request_repaint),
+        content_hash_sender,
         cancellation_token,
     );
 
@@ -45,15 +102,29 @@ pub fn send(
 
 pub struct SendingController {
     progress_receiver: svc::Receiver<SendingProgress>,
+    verification_sender: Option<oneshot::Sender<()>>,
+    content_hash_receiver: BorrowingOneshotReceiver<ContentHash>,
     cancellation_source: CancellationSource,
 }
 
 pub enum SendingProgress {
-    Packing,
+    Packing(PackingProgress),
     Connecting,
     Connected(Code),
-    PreparingToSend,
-    Sending(Arc<TransitInfo>, Progress),
+    /// Waiting for the user to confirm that the peer sees the same verifier.
+    Verifying(Verifier),
+    PreparingToSend(Verifier),
+    Sending(Arc<TransitInfo>, Progress, TransferRate),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackingProgress {
+    /// The number of files that have been scanned out of the total number of files.
+    pub files: Progress,
+    /// The size of the files that have been scanned out of the total size of all files.
+    pub bytes: Progress,
+    /// The path of the file that was scanned last, relative to the Zip file.
+    pub current_file: PathBuf,
 }
 
 impl SendingController {
@@ -61,61 +132,197 @@ impl SendingController {
         self.progress_receiver.latest()
     }
 
+    /// Lets the transfer continue once the user has compared the verifier with the peer.
+    pub fn confirm_verification(&mut self) {
+        if let Some(sender) = self.verification_sender.take() {
+            _ = sender.send(());
+        }
+    }
+
+    /// The digest of the sent file, once the transfer has been completed.
+    /// Text messages don't have one.
+    pub fn content_hash(&mut self) -> Option<&ContentHash> {
+        self.content_hash_receiver.value()
+    }
+
     pub fn cancel(&mut self) {
         self.cancellation_source.cancel()
     }
 }
 
+/// How the connection to the peer is established.
+struct PeerConnection {
+    code: SendCode,
+    verification: Option<oneshot::Receiver<()>>,
+}
+
 async fn send_impl(
     send_request: SendRequest,
+    connection_settings: ConnectionSettings,
+    peer_connection: PeerConnection,
     mut report: impl Reporter,
+    content_hash_sender: ::oneshot::Sender<ContentHash>,
     cancellation: CancellationToken,
 ) -> Result<(), (PortalError, SendRequest)> {
-    report(SendingProgress::Packing);
+    if let SendRequest::Text(text) = &send_request {
+        return send_text(
+            text,
+            &connection_settings,
+            peer_connection,
+            report,
+            cancellation,
+        )
+        .await
+        .with_send_request(send_request);
+    }
+    let file_request = send_request
+        .file_request()
+        .expect("everything but text should be sent as a file");
+
+    report(SendingProgress::Packing(PackingProgress::default()));
+    let mut packing_report = report.clone();
     let sendable_file = Abortable::new(
-        SendableFile::from_send_request(send_request.clone(), cancellation.clone()),
+        SendableFile::from_file_request(
+            file_request,
+            move |progress| packing_report(SendingProgress::Packing(progress)),
+            cancellation.clone(),
+        ),
         cancellation.as_abort_registration(),
     )
     .await
     .with_send_request(send_request.clone())?
     .with_send_request(send_request.clone())?;
-    send_impl_with_sendable_file(&sendable_file, report, cancellation)
-        .await
-        .with_send_request(SendRequest::new_cached(sendable_file, send_request))
+    send_impl_with_sendable_file(
+        &sendable_file,
+        &connection_settings,
+        peer_connection,
+        report,
+        content_hash_sender,
+        cancellation,
+    )
+    .await
+    .with_send_request(SendRequest::new_cached(sendable_file, send_request))
 }
 
 async fn send_impl_with_sendable_file(
     sendable_file: &SendableFile,
-    mut report: impl Reporter,
+    connection_settings: &ConnectionSettings,
+    peer_connection: PeerConnection,
+    report: impl Reporter,
+    content_hash_sender: ::oneshot::Sender<ContentHash>,
     cancellation: CancellationToken,
 ) -> Result<(), PortalError> {
     let (transit_info_receiver, transit_info_updater) = svc::channel();
+    let relay_hints = connection_settings.relay_hints()?;
+    let wormhole = connect_to_peer(
+        connection_settings,
+        peer_connection,
+        report.clone(),
+        &cancellation,
+    )
+    .await?;
+    let mut wormhole = wormhole;
+    let progress_handler = progress_handler(transit_info_receiver, report.clone());
+    let transit_handler = transit_handler(transit_info_updater, report);
+
+    let result = match sendable_file {
+        SendableFile::Path(path) => {
+            until_canceled(
+                send_file(
+                    &mut wormhole,
+                    relay_hints,
+                    path,
+                    progress_handler,
+                    transit_handler,
+                ),
+                cancellation.as_future(),
+            )
+            .await
+        }
+        SendableFile::Directory(directory_name, zip) => {
+            until_canceled(
+                send_directory(
+                    &mut wormhole,
+                    relay_hints,
+                    &directory_name.to_string_lossy(),
+                    zip,
+                    progress_handler,
+                    transit_handler,
+                    cancellation.clone(),
+                ),
+                cancellation.as_future(),
+            )
+            .await
+        }
+    };
+    let content_hash = finish(wormhole, result).await?;
+    _ = content_hash_sender.send(content_hash);
+    Ok(())
+}
 
+async fn send_text(
+    text: &str,
+    connection_settings: &ConnectionSettings,
+    peer_connection: PeerConnection,
+    report: impl Reporter,
+    cancellation: CancellationToken,
+) -> Result<(), PortalError> {
+    let mut wormhole =
+        connect_to_peer(connection_settings, peer_connection, report, &cancellation).await?;
+    let result = until_canceled(exchange_text(&mut wormhole, text), cancellation.as_future()).await;
+    finish(wormhole, result).await
+}
+
+async fn exchange_text(wormhole: &mut Wormhole, text: &str) -> Result<(), PortalError> {
+    let offer = PeerMessage::Offer(OfferMessage::Message(text.to_owned()));
+    send_message(wormhole, &offer).await?;
+
+    loop {
+        match receive_message(wormhole).await? {
+            // Receivers send their transit hints before they know what we're offering.
+            PeerMessage::Transit(_) => continue,
+            PeerMessage::Answer(AnswerMessage::MessageAck(ack)) if ack == "ok" => return Ok(()),
+            PeerMessage::Answer(AnswerMessage::MessageAck(_)) => {
+                return Err(TransferError::AckError.into())
+            }
+            message => return Err(unexpected_message("answer/message_ack", &message)),
+        }
+    }
+}
+
+async fn connect_to_peer(
+    connection_settings: &ConnectionSettings,
+    peer_connection: PeerConnection,
+    mut report: impl Reporter,
+    cancellation: &CancellationToken,
+) -> Result<Wormhole, PortalError> {
     report(SendingProgress::Connecting);
     let wormhole = async {
-        let (code, wormhole_future) = connect().await?;
+        let (code, wormhole_future) = connect(connection_settings, peer_connection.code).await?;
         report(SendingProgress::Connected(code));
-
-        let wormhole = wormhole_future.await?;
-        report(SendingProgress::PreparingToSend);
-
-        Result::<_, PortalError>::Ok(wormhole)
+        wormhole_future.await
     };
-
     let wormhole = Abortable::new(wormhole, cancellation.as_abort_registration()).await??;
 
-    send_file(
-        wormhole,
-        sendable_file,
-        progress_handler(transit_info_receiver, report.clone()),
-        transit_handler(transit_info_updater, report),
-        cancellation.as_future(),
-    )
-    .await
+    let verifier = Verifier::new(wormhole.verifier());
+    if let Some(verification) = peer_connection.verification {
+        report(SendingProgress::Verifying(verifier.clone()));
+        // The peer is already waiting for our offer, so it's told when we don't continue.
+        let verified = until_canceled(
+            async { verification.await.map_err(|_| PortalError::Canceled) },
+            cancellation.as_future(),
+        )
+        .await;
+        if let Err(error) = verified {
+            return finish(wormhole, Err(error)).await;
+        }
+    }
+    report(SendingProgress::PreparingToSend(verifier));
+    Ok(wormhole)
 }
 
 trait_set! {
-    trait Reporter = FnMut(SendingProgress) + Clone + 'static;
+    trait Reporter = FnMut(SendingProgress) + Clone + Send + 'static;
 }
 
 fn report(
@@ -135,7 +342,11 @@ fn transit_handler(
     move |transit_info| {
         let transit_info = Arc::new(transit_info);
         _ = updater.update(Some(Arc::clone(&transit_info)));
-        report(SendingProgress::Sending(transit_info, Progress::default()));
+        report(SendingProgress::Sending(
+            transit_info,
+            Progress::default(),
+            TransferRate::default(),
+        ));
     }
 }
 
@@ -143,59 +354,176 @@ fn progress_handler(
     mut transit_info: svc::Receiver<Option<Arc<TransitInfo>>>,
     mut report: impl Reporter,
 ) -> impl ProgressHandler {
+    let mut rate_estimator = None;
     move |value, total| match transit_info.latest().clone() {
         None => warn!("transit info unexpectedly missing in progress handler"),
         Some(transit_info) => report(SendingProgress::Sending(
             transit_info,
             Progress { value, total },
+            rate_estimator
+                .get_or_insert_with(RateEstimator::default)
+                .update(value, total),
         )),
     }
 }
 
+/// Offers a single file, which the receiver may resume if it has kept a part of it
+/// from an interrupted transfer.
 async fn send_file(
-    wormhole: Wormhole,
-    sendable_file: &SendableFile,
+    wormhole: &mut Wormhole,
+    relay_hints: Vec<RelayHint>,
+    path: &Path,
     progress_handler: impl ProgressHandler,
     transit_handler: impl TransitHandler,
-    cancel: impl Future<Output = ()>,
-) -> Result<(), PortalError> {
-    let mut file = File::open(sendable_file.path()).await?;
-    let metadata = file.metadata().await?;
-    let file_size = metadata.len();
-
-    let mut canceled = false;
-    transfer::send_file(
+) -> Result<ContentHash, PortalError> {
+    let mut file = File::open(path).await?;
+    let file_size = file.metadata().await?.len();
+    let offer = OfferMessage::File {
+        filename: path
+            .file_name()
+            .expect("path should be absolute")
+            .to_string_lossy()
+            .into_owned(),
+        filesize: file_size,
+        resumable: true,
+    };
+    send_offer(
         wormhole,
-        RELAY_HINTS.clone(),
-        &mut file,
-        sendable_file.file_name().to_string_lossy(),
+        relay_hints,
+        offer,
         file_size,
-        Abilities::ALL,
+        &mut file,
+        progress_handler,
         transit_handler,
+    )
+    .await
+}
+
+/// Offers a directory, which the receiver unpacks, the way the Python client does.
+/// `magic_wormhole` only sends folders as tar files, so this is implemented on our own.
+async fn send_directory(
+    wormhole: &mut Wormhole,
+    relay_hints: Vec<RelayHint>,
+    directory_name: &str,
+    zip: &Arc<StreamingZip>,
+    progress_handler: impl ProgressHandler,
+    transit_handler: impl TransitHandler,
+    cancellation: CancellationToken,
+) -> Result<ContentHash, PortalError> {
+    let offer = OfferMessage::Directory {
+        dirname: directory_name.to_owned(),
+        mode: DIRECTORY_MODE.to_owned(),
+        zipsize: zip.size(),
+        numbytes: zip.num_bytes(),
+        numfiles: zip.num_files(),
+    };
+    let mut reader = zip.reader(cancellation);
+    send_offer(
+        wormhole,
+        relay_hints,
+        offer,
+        zip.size(),
+        &mut reader,
         progress_handler,
-        async {
-            cancel.await;
-            canceled = true;
-        },
+        transit_handler,
     )
-    .await?;
+    .await
+}
 
-    if canceled {
-        Err(PortalError::Canceled)
-    } else {
-        Ok(())
+/// Sends the offer and, once it's accepted, the `size` bytes of the reader over a transit connection.
+/// Returns the digest of the sent bytes, which the receiver has acknowledged.
+async fn send_offer(
+    wormhole: &mut Wormhole,
+    relay_hints: Vec<RelayHint>,
+    offer: OfferMessage,
+    size: u64,
+    reader: &mut (impl AsyncRead + Unpin),
+    progress_handler: impl ProgressHandler,
+    transit_handler: impl TransitHandler,
+) -> Result<ContentHash, PortalError> {
+    let resumable = matches!(
+        offer,
+        OfferMessage::File {
+            resumable: true,
+            ..
+        }
+    );
+    let connector = init_transit(relay_hints).await?;
+    send_message(wormhole, &PeerMessage::transit(&connector)).await?;
+    send_message(wormhole, &PeerMessage::Offer(offer)).await?;
+
+    let (their_transit, resume_from) = receive_file_ack(wormhole, resumable).await?;
+    // The prefix is checked before connecting, so that a mismatch is reported over the wormhole.
+    let prefix = match resume_from {
+        Some((offset, sha256)) => resumed_prefix(reader, offset, &sha256).await?,
+        None => Prefix::default(),
+    };
+    let (mut transit, transit_info) = connector
+        .leader_connect(
+            transit_key(wormhole),
+            their_transit.abilities_v1,
+            Arc::new(their_transit.hints_v1),
+        )
+        .await?;
+    transit_handler(transit_info);
+
+    let sha256 = send_records(&mut transit, size, prefix, progress_handler, reader).await?;
+    send_content_hash(wormhole, sha256).await
+}
+
+/// Waits for the receiver to accept the offer and returns its transit hints,
+/// which may arrive before or after the answer, and where the receiver wants to resume.
+async fn receive_file_ack(
+    wormhole: &mut Wormhole,
+    resumable: bool,
+) -> Result<(TransitMessage, Option<(u64, String)>), PortalError> {
+    let mut their_transit = None;
+    let mut accepted = None;
+    loop {
+        match receive_message(wormhole).await? {
+            PeerMessage::Transit(transit) => their_transit = Some(transit),
+            PeerMessage::Answer(AnswerMessage::FileAck(ack)) if ack == "ok" => {
+                accepted = Some(None)
+            }
+            PeerMessage::Answer(AnswerMessage::FileAck(_)) => {
+                return Err(TransferError::AckError.into())
+            }
+            PeerMessage::Answer(AnswerMessage::FileResume { offset, sha256 }) if resumable => {
+                accepted = Some(Some((offset, sha256)))
+            }
+            message => return Err(unexpected_message("answer/file_ack", &message)),
+        }
+
+        if let (Some(_), Some(_)) = (&accepted, &their_transit) {
+            let resume_from = accepted.expect("checked above");
+            return Ok((their_transit.expect("checked above"), resume_from));
+        }
     }
 }
 
-async fn connect() -> Result<(Code, BoxFuture<'static, Result<Wormhole, PortalError>>), PortalError>
-{
-    let mailbox = MailboxConnection::create(transfer::APP_CONFIG, 4).await?;
+async fn connect(
+    connection_settings: &ConnectionSettings,
+    code: SendCode,
+) -> Result<(Code, BoxFuture<'static, Result<Wormhole, PortalError>>), PortalError> {
+    let app_config = connection_settings.app_config();
+    let mailbox = match code {
+        SendCode::Generated { word_count } => {
+            MailboxConnection::create(app_config, word_count).await?
+        }
+        SendCode::Custom(code) => MailboxConnection::connect(app_config, code, true).await?,
+        // The peer has already allocated the nameplate, if it's missing the peer is gone.
+        SendCode::Peer(code) => MailboxConnection::connect(app_config, code, false).await?,
+    };
     let code = mailbox.code().clone();
     let future = Wormhole::connect(mailbox);
     Ok((code, Box::pin(async { Ok(future.await?) })))
 }
 
 trait ResultExt<T> {
+    #[allow(
+        clippy::result_large_err,
+        reason = "the request is handed back with the error so that the transfer can be retried"
+    )]
     fn with_send_request(self, send_request: SendRequest) -> Result<T, (PortalError, SendRequest)>;
 }
 
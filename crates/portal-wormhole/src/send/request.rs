@@ -8,6 +8,8 @@ pub enum SendRequest {
     File(PathBuf),
     Folder(PathBuf),
     Selection(Vec<PathBuf>),
+    /// A text message, which is sent without a transit connection.
+    Text(String),
     Cached(Box<SendRequest>, CachedSendRequest),
 }
 
@@ -40,3 +42,25 @@ impl SendRequest {
         }
     }
 }
+
+/// A request that is sent as a file over a transit connection.
+#[derive(Clone, Debug)]
+pub(crate) enum FileRequest {
+    File(PathBuf),
+    Folder(PathBuf),
+    Selection(Vec<PathBuf>),
+    Cached(CachedSendRequest),
+}
+
+impl SendRequest {
+    /// `None` for text messages, which are sent without a transit connection.
+    pub(crate) fn file_request(&self) -> Option<FileRequest> {
+        match self {
+            SendRequest::File(path) => Some(FileRequest::File(path.clone())),
+            SendRequest::Folder(path) => Some(FileRequest::Folder(path.clone())),
+            SendRequest::Selection(paths) => Some(FileRequest::Selection(paths.clone())),
+            SendRequest::Text(_) => None,
+            SendRequest::Cached(_, cached) => Some(FileRequest::Cached(cached.clone())),
+        }
+    }
+}
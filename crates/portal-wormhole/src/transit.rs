@@ -1,13 +1,9 @@
-use crate::{Progress, RequestRepaint};
-use lazy_static::lazy_static;
-use magic_wormhole::transit::{RelayHint, TransitInfo, DEFAULT_RELAY_SERVER};
+use crate::rate::RateEstimator;
+use crate::{Progress, RequestRepaint, TransferRate};
+use magic_wormhole::transit::{RelayHint, RelayHintParseError, TransitInfo, DEFAULT_RELAY_SERVER};
 use single_value_channel as svc;
 use url::Url;
 
-lazy_static! {
-    pub static ref RELAY_HINTS: Vec<RelayHint> = relay_hints();
-}
-
 pub trait TransitHandler: FnOnce(TransitInfo) {}
 
 impl<F> TransitHandler for F where F: FnOnce(TransitInfo) {}
@@ -28,21 +24,31 @@ pub fn transit_handler(
 
 pub fn progress_handler(
     updater: svc::Updater<Progress>,
+    rate_updater: svc::Updater<TransferRate>,
     mut request_repaint: impl RequestRepaint,
 ) -> impl ProgressHandler {
+    let mut rate_estimator = None;
     move |value, total| {
         _ = updater.update(Progress { value, total });
+        _ = rate_updater.update(
+            rate_estimator
+                .get_or_insert_with(RateEstimator::default)
+                .update(value, total),
+        );
         request_repaint();
     }
 }
 
-fn relay_hints() -> Vec<RelayHint> {
-    let hint = RelayHint::from_urls(None, [default_relay_server()])
-        .expect("constant relay hints should be valid");
-    vec![hint]
+/// Each relay server gets its own hint, as the URLs of a single hint
+/// are expected to be different endpoints of the same server.
+pub fn relay_hints(relay_servers: &[Url]) -> Result<Vec<RelayHint>, RelayHintParseError> {
+    relay_servers
+        .iter()
+        .map(|url| RelayHint::from_urls(None, [url.clone()]))
+        .collect()
 }
 
-fn default_relay_server() -> Url {
+pub fn default_relay_server() -> Url {
     DEFAULT_RELAY_SERVER
         .parse()
         .expect("constant URL should be valid")
@@ -0,0 +1,127 @@
+use std::fmt;
+
+/// Number of symbols shown, each encoding 6 bits of the verifier.
+const SYMBOL_COUNT: usize = 7;
+
+/// The same symbols as used by Matrix' emoji verification,
+/// chosen to be easy to recognize and to describe over the phone.
+const SYMBOLS: [(&str, &str); 64] = [
+    ("🐶", "Dog"),
+    ("🐱", "Cat"),
+    ("🦁", "Lion"),
+    ("🐎", "Horse"),
+    ("🦄", "Unicorn"),
+    ("🐷", "Pig"),
+    ("🐘", "Elephant"),
+    ("🐰", "Rabbit"),
+    ("🐼", "Panda"),
+    ("🐓", "Rooster"),
+    ("🐧", "Penguin"),
+    ("🐢", "Turtle"),
+    ("🐟", "Fish"),
+    ("🐙", "Octopus"),
+    ("🦋", "Butterfly"),
+    ("🌷", "Flower"),
+    ("🌳", "Tree"),
+    ("🌵", "Cactus"),
+    ("🍄", "Mushroom"),
+    ("🌏", "Globe"),
+    ("🌙", "Moon"),
+    ("☁", "Cloud"),
+    ("🔥", "Fire"),
+    ("🍌", "Banana"),
+    ("🍎", "Apple"),
+    ("🍓", "Strawberry"),
+    ("🌽", "Corn"),
+    ("🍕", "Pizza"),
+    ("🎂", "Cake"),
+    ("❤", "Heart"),
+    ("😀", "Smiley"),
+    ("🤖", "Robot"),
+    ("🎩", "Hat"),
+    ("👓", "Glasses"),
+    ("🔧", "Spanner"),
+    ("🎅", "Santa"),
+    ("👍", "Thumbs Up"),
+    ("☂", "Umbrella"),
+    ("⌛", "Hourglass"),
+    ("⏰", "Clock"),
+    ("🎁", "Gift"),
+    ("💡", "Light Bulb"),
+    ("📕", "Book"),
+    ("✏", "Pencil"),
+    ("📎", "Paperclip"),
+    ("✂", "Scissors"),
+    ("🔒", "Lock"),
+    ("🔑", "Key"),
+    ("🔨", "Hammer"),
+    ("☎", "Telephone"),
+    ("🏁", "Flag"),
+    ("🚂", "Train"),
+    ("🚲", "Bicycle"),
+    ("✈", "Aeroplane"),
+    ("🚀", "Rocket"),
+    ("🏆", "Trophy"),
+    ("⚽", "Ball"),
+    ("🎸", "Guitar"),
+    ("🎺", "Trumpet"),
+    ("🔔", "Bell"),
+    ("⚓", "Anchor"),
+    ("🎧", "Headphones"),
+    ("📁", "Folder"),
+    ("📌", "Pin"),
+];
+
+/// A short representation of the secret that both peers derive from the code.
+/// If both sides see the same symbols, they are connected to each other
+/// and not to someone who intercepted the code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verifier {
+    symbols: [u8; SYMBOL_COUNT],
+}
+
+impl Verifier {
+    pub(crate) fn new(verifier: &[u8]) -> Self {
+        let mut symbols = [0; SYMBOL_COUNT];
+        for (index, symbol) in symbols.iter_mut().enumerate() {
+            let bit = index * 6;
+            let bits = u16::from_be_bytes([verifier[bit / 8], verifier[bit / 8 + 1]]);
+            *symbol = (bits >> (10 - bit % 8) & 0x3f) as u8;
+        }
+        Verifier { symbols }
+    }
+
+    /// The emoji and their names.
+    pub fn symbols(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        self.symbols
+            .iter()
+            .map(|symbol| SYMBOLS[usize::from(*symbol)])
+    }
+
+    pub fn emoji(&self) -> String {
+        self.symbols()
+            .map(|(emoji, _)| emoji)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Formats the names of the symbols, e.g. "Dog, Cat, Lion, …".
+impl fmt::Display for Verifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.symbols().map(|(_, name)| name).collect();
+        write!(f, "{}", names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_six_bits_per_symbol() {
+        // 000000 000001 000010 000011 111111 ...
+        let verifier = Verifier::new(&[0x00, 0x10, 0x83, 0xfc, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(verifier.to_string(), "Dog, Cat, Lion, Horse, Pin, Dog, Dog");
+    }
+}
@@ -0,0 +1,853 @@
+//! Packs files and folders as a Zip archive on the fly while they are being sent.
+//!
+//! The transfer protocol needs to know the size of the file up front.
+//! Files are deflated into uncompressed blocks only, which makes the size of the archive
+//! deterministic, so it can be computed from a scan of the files before packing starts.
+//! Deflating rather than storing them is what the `zipfile/deflated` mode of directory offers promises.
+//! The checksums of the entries are only known after they've been written,
+//! so they're stored in a data descriptor after each entry.
+
+use crate::cancellation::CancellationToken;
+use crate::send::PackingProgress;
+use crate::{PortalError, Progress};
+use async_std::task::spawn_blocking;
+use crc32fast::Hasher;
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::{AsyncRead, SinkExt, TryStreamExt};
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Write};
+use std::ops::Add;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const VERSION_MADE_BY_UNIX: u16 = 3 << 8;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Uncompressed deflate blocks have a 16 bit length.
+const MAX_BLOCK_SIZE: usize = u16::MAX as usize;
+const BLOCK_HEADER_SIZE: u64 = 5;
+const CHANNEL_CAPACITY: usize = 16;
+const REPORT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A Zip archive that has not been packed yet.
+#[derive(Debug)]
+pub(crate) struct StreamingZip {
+    entries: Vec<Entry>,
+    size: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    /// The file to read the contents from, `None` for folders.
+    source: Option<PathBuf>,
+    /// The size of the file before it's deflated.
+    size: u64,
+    modified: (u16, u16),
+    mode: u32,
+    offset: u64,
+}
+
+/// Scans a folder recursively to prepare it for packing.
+/// The entries of the archive are relative to the folder.
+pub(crate) fn prepare_folder_zip(
+    folder_path: &Path,
+    progress_handler: impl FnMut(PackingProgress),
+    cancellation: CancellationToken,
+) -> Result<StreamingZip, PortalError> {
+    let totals = count_files(folder_path, &cancellation)?;
+    let mut builder = Builder::new(totals, progress_handler);
+    builder.add_folder(folder_path, None, &cancellation)?;
+    Ok(builder.finish())
+}
+
+/// Scans a selection of paths (e.g. from drag and drop) to prepare it for packing.
+///
+/// Note that this function does not handle duplicate entries as this should be a rare case
+/// (it requires selecting files across multiple directories).
+pub(crate) fn prepare_selection_zip(
+    paths: &[PathBuf],
+    progress_handler: impl FnMut(PackingProgress),
+    cancellation: CancellationToken,
+) -> Result<StreamingZip, PortalError> {
+    let totals = paths.iter().try_fold(Totals::default(), |totals, path| {
+        Ok::<_, PortalError>(totals + count_files(path, &cancellation)?)
+    })?;
+    let mut builder = Builder::new(totals, progress_handler);
+    for path in paths {
+        builder.add_path(path, &cancellation)?;
+    }
+    Ok(builder.finish())
+}
+
+impl StreamingZip {
+    /// The exact size of the packed archive.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub(crate) fn num_files(&self) -> u64 {
+        self.files().count() as u64
+    }
+
+    /// The total size of the files before packing.
+    pub(crate) fn num_bytes(&self) -> u64 {
+        self.files().map(|entry| entry.size).sum()
+    }
+
+    fn files(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|entry| entry.source.is_some())
+    }
+
+    /// Packs the archive on a background thread. Reading stops with an error
+    /// if the files were changed after they were scanned.
+    pub(crate) fn reader(
+        self: &Arc<Self>,
+        cancellation: CancellationToken,
+    ) -> impl AsyncRead + Unpin + Send {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let zip = Arc::clone(self);
+        _ = spawn_blocking(move || {
+            let mut writer = ChannelWriter::new(sender);
+            let result = zip
+                .write(&mut writer, &cancellation)
+                .and_then(|_| Ok(writer.flush()?));
+            if let Err(error) = result {
+                writer.send(Err(io::Error::other(error.to_string())));
+            }
+        });
+        receiver.into_async_read()
+    }
+
+    fn write(
+        &self,
+        writer: &mut impl Write,
+        cancellation: &CancellationToken,
+    ) -> Result<(), PortalError> {
+        let mut checksums = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            cancellation.error_if_canceled()?;
+            writer.write_all(&entry.local_header())?;
+            let checksum = match &entry.source {
+                Some(source) => {
+                    let checksum = deflate_file(source, entry.size, writer, cancellation)?;
+                    writer.write_all(&entry.data_descriptor(checksum))?;
+                    checksum
+                }
+                None => 0,
+            };
+            checksums.push(checksum);
+        }
+
+        let central_directory_offset = self.entries.last().map_or(0, Entry::end_offset);
+        let mut central_directory_size = 0;
+        for (entry, checksum) in self.entries.iter().zip(checksums) {
+            let header = entry.central_directory_header(checksum);
+            central_directory_size += header.len() as u64;
+            writer.write_all(&header)?;
+        }
+        writer.write_all(&end_of_central_directory(
+            self.entries.len() as u64,
+            central_directory_offset,
+            central_directory_size,
+        ))?;
+        Ok(())
+    }
+}
+
+/// The number of files and their total size, which the progress of the scan is measured against.
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    files: u64,
+    bytes: u64,
+}
+
+impl Add for Totals {
+    type Output = Totals;
+
+    fn add(self, other: Totals) -> Totals {
+        Totals {
+            files: self.files + other.files,
+            bytes: self.bytes + other.bytes,
+        }
+    }
+}
+
+/// Counts the files and adds up their sizes in a single walk, which is
+/// considerably faster than the actual scan for large folders as no entries are built.
+fn count_files(path: &Path, cancellation: &CancellationToken) -> Result<Totals, PortalError> {
+    let mut totals = Totals::default();
+    for entry in WalkDir::new(path).follow_links(true) {
+        cancellation.error_if_canceled()?;
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            totals.files += 1;
+            totals.bytes += entry.metadata()?.len();
+        }
+    }
+    Ok(totals)
+}
+
+struct Builder<F> {
+    entries: Vec<Entry>,
+    progress: PackingProgress,
+    progress_handler: F,
+    last_report: Option<Instant>,
+}
+
+impl<F> Builder<F>
+where
+    F: FnMut(PackingProgress),
+{
+    fn new(totals: Totals, progress_handler: F) -> Self {
+        Builder {
+            entries: Vec::new(),
+            progress: PackingProgress {
+                files: Progress {
+                    value: 0,
+                    total: totals.files,
+                },
+                bytes: Progress {
+                    value: 0,
+                    total: totals.bytes,
+                },
+                ..PackingProgress::default()
+            },
+            progress_handler,
+            last_report: None,
+        }
+    }
+
+    /// Adds a file or folder to the archive.
+    ///
+    /// Symbolic links are materialized (i.e. resolved and the real files or folders are added to the Zip file).
+    fn add_path(
+        &mut self,
+        path: &Path,
+        cancellation: &CancellationToken,
+    ) -> Result<(), PortalError> {
+        cancellation.error_if_canceled()?;
+
+        let relative_path = Path::new(path.file_name().expect("path should be absolute"));
+        let metadata = fs::metadata(path)?;
+        if metadata.is_dir() {
+            self.add_folder(path, Some(relative_path), cancellation)
+        } else {
+            self.push(relative_path, Some(path), &metadata);
+            Ok(())
+        }
+    }
+
+    /// Adds a folder by recursively walking through the directory.
+    ///
+    /// Symbolic links are materialized (i.e. resolved and the real files are added to the Zip file). \
+    /// This is the default behaviour of the `zip` tool and best for cross-platform compatibility.
+    fn add_folder(
+        &mut self,
+        folder_path: &Path,
+        folder_relative_path: Option<&Path>,
+        cancellation: &CancellationToken,
+    ) -> Result<(), PortalError> {
+        for entry in WalkDir::new(folder_path).follow_links(true) {
+            cancellation.error_if_canceled()?;
+
+            let entry = entry?;
+            let relative_path = entry
+                .path()
+                .strip_prefix(folder_path)
+                .expect("File in folder should start with folder path");
+            let relative_path = match folder_relative_path {
+                Some(folder_relative_path) => folder_relative_path.join(relative_path),
+                None if relative_path.as_os_str().is_empty() => continue,
+                None => relative_path.to_owned(),
+            };
+            let metadata = entry.metadata()?;
+            if !metadata.is_dir() && !metadata.is_file() {
+                continue;
+            }
+            let source = metadata.is_file().then(|| entry.path());
+            self.push(&relative_path, source, &metadata);
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, relative_path: &Path, source: Option<&Path>, metadata: &Metadata) {
+        let mut name = entry_name(relative_path);
+        if source.is_none() {
+            name.push('/');
+        }
+        let entry = Entry {
+            name,
+            source: source.map(Path::to_owned),
+            size: if source.is_some() { metadata.len() } else { 0 },
+            modified: dos_date_time(metadata.modified().unwrap_or(UNIX_EPOCH)),
+            mode: mode(metadata),
+            offset: self.entries.last().map_or(0, Entry::end_offset),
+        };
+        if source.is_some() {
+            self.report(relative_path, entry.size);
+        }
+        self.entries.push(entry);
+    }
+
+    fn report(&mut self, relative_path: &Path, size: u64) {
+        self.progress.files.value += 1;
+        self.progress.bytes.value += size;
+
+        // Reporting every file would be too much for folders with many small files.
+        let now = Instant::now();
+        let is_last = self.progress.files.value >= self.progress.files.total;
+        if is_last
+            || self
+                .last_report
+                .is_none_or(|last| now - last >= REPORT_INTERVAL)
+        {
+            self.last_report = Some(now);
+            self.progress.current_file = relative_path.to_owned();
+            (self.progress_handler)(self.progress.clone());
+        }
+    }
+
+    fn finish(self) -> StreamingZip {
+        let central_directory_offset = self.entries.last().map_or(0, Entry::end_offset);
+        let central_directory_size = self
+            .entries
+            .iter()
+            .map(|entry| entry.central_directory_header(0).len() as u64)
+            .sum();
+        let end_size = end_of_central_directory(
+            self.entries.len() as u64,
+            central_directory_offset,
+            central_directory_size,
+        )
+        .len() as u64;
+        StreamingZip {
+            size: central_directory_offset + central_directory_size + end_size,
+            entries: self.entries,
+        }
+    }
+}
+
+impl Entry {
+    fn is_zip64(&self) -> bool {
+        self.compressed_size() >= u32::MAX as u64
+    }
+
+    fn method(&self) -> u16 {
+        match self.source {
+            Some(_) => METHOD_DEFLATED,
+            None => METHOD_STORED,
+        }
+    }
+
+    /// The size of the deflated file, which is always a bit larger than the file.
+    fn compressed_size(&self) -> u64 {
+        match self.source {
+            Some(_) => deflated_size(self.size),
+            None => 0,
+        }
+    }
+
+    fn version(&self) -> u16 {
+        if self.is_zip64() {
+            VERSION_ZIP64
+        } else {
+            VERSION_DEFAULT
+        }
+    }
+
+    fn flags(&self) -> u16 {
+        if self.source.is_some() {
+            FLAG_UTF8 | FLAG_DATA_DESCRIPTOR
+        } else {
+            FLAG_UTF8
+        }
+    }
+
+    /// The offset right after this entry, which is where the next entry starts.
+    fn end_offset(&self) -> u64 {
+        let data_descriptor_size = match self.source {
+            Some(_) => self.data_descriptor(0).len() as u64,
+            None => 0,
+        };
+        self.offset
+            + self.local_header().len() as u64
+            + self.compressed_size()
+            + data_descriptor_size
+    }
+
+    fn local_header(&self) -> Vec<u8> {
+        // The checksum and sizes are in the data descriptor.
+        let (size, extra) = if self.is_zip64() {
+            (u32::MAX, zip64_extra_field(&[0, 0]))
+        } else {
+            (0, Vec::new())
+        };
+        let mut header = Vec::with_capacity(30 + self.name.len() + extra.len());
+        put_u32(&mut header, LOCAL_FILE_HEADER_SIGNATURE);
+        put_u16(&mut header, self.version());
+        put_u16(&mut header, self.flags());
+        put_u16(&mut header, self.method());
+        put_u16(&mut header, self.modified.0);
+        put_u16(&mut header, self.modified.1);
+        put_u32(&mut header, 0);
+        put_u32(&mut header, size);
+        put_u32(&mut header, size);
+        put_u16(&mut header, self.name.len() as u16);
+        put_u16(&mut header, extra.len() as u16);
+        header.extend_from_slice(self.name.as_bytes());
+        header.extend_from_slice(&extra);
+        header
+    }
+
+    fn data_descriptor(&self, checksum: u32) -> Vec<u8> {
+        let mut descriptor = Vec::with_capacity(24);
+        put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+        put_u32(&mut descriptor, checksum);
+        if self.is_zip64() {
+            put_u64(&mut descriptor, self.compressed_size());
+            put_u64(&mut descriptor, self.size);
+        } else {
+            put_u32(&mut descriptor, self.compressed_size() as u32);
+            put_u32(&mut descriptor, self.size as u32);
+        }
+        descriptor
+    }
+
+    fn central_directory_header(&self, checksum: u32) -> Vec<u8> {
+        // The zip64 extra field lists the sizes in the opposite order of the header.
+        let mut zip64_values = Vec::new();
+        let size = clamp_to_u32(self.size, &mut zip64_values);
+        let compressed_size = clamp_to_u32(self.compressed_size(), &mut zip64_values);
+        let offset = clamp_to_u32(self.offset, &mut zip64_values);
+        let extra = if zip64_values.is_empty() {
+            Vec::new()
+        } else {
+            zip64_extra_field(&zip64_values)
+        };
+        let version = if zip64_values.is_empty() {
+            VERSION_DEFAULT
+        } else {
+            VERSION_ZIP64
+        };
+
+        let mut header = Vec::with_capacity(46 + self.name.len() + extra.len());
+        put_u32(&mut header, CENTRAL_DIRECTORY_HEADER_SIGNATURE);
+        put_u16(&mut header, VERSION_MADE_BY_UNIX | version);
+        put_u16(&mut header, version);
+        put_u16(&mut header, self.flags());
+        put_u16(&mut header, self.method());
+        put_u16(&mut header, self.modified.0);
+        put_u16(&mut header, self.modified.1);
+        put_u32(&mut header, checksum);
+        put_u32(&mut header, compressed_size);
+        put_u32(&mut header, size);
+        put_u16(&mut header, self.name.len() as u16);
+        put_u16(&mut header, extra.len() as u16);
+        put_u16(&mut header, 0); // Comment length
+        put_u16(&mut header, 0); // Disk number
+        put_u16(&mut header, 0); // Internal attributes
+        put_u32(&mut header, self.external_attributes());
+        put_u32(&mut header, offset);
+        header.extend_from_slice(self.name.as_bytes());
+        header.extend_from_slice(&extra);
+        header
+    }
+
+    fn external_attributes(&self) -> u32 {
+        const MS_DOS_DIRECTORY: u32 = 0x10;
+        match self.source {
+            Some(_) => self.mode << 16,
+            None => self.mode << 16 | MS_DOS_DIRECTORY,
+        }
+    }
+}
+
+fn end_of_central_directory(entries: u64, offset: u64, size: u64) -> Vec<u8> {
+    let needs_zip64 =
+        entries >= u16::MAX as u64 || offset >= u32::MAX as u64 || size >= u32::MAX as u64;
+    let mut record = Vec::with_capacity(98);
+    if needs_zip64 {
+        let zip64_record_offset = offset + size;
+        put_u32(&mut record, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        put_u64(&mut record, 44); // Size of the remaining record
+        put_u16(&mut record, VERSION_MADE_BY_UNIX | VERSION_ZIP64);
+        put_u16(&mut record, VERSION_ZIP64);
+        put_u32(&mut record, 0); // Disk number
+        put_u32(&mut record, 0); // Disk with the central directory
+        put_u64(&mut record, entries);
+        put_u64(&mut record, entries);
+        put_u64(&mut record, size);
+        put_u64(&mut record, offset);
+
+        put_u32(
+            &mut record,
+            ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE,
+        );
+        put_u32(&mut record, 0); // Disk with the zip64 end of central directory
+        put_u64(&mut record, zip64_record_offset);
+        put_u32(&mut record, 1); // Total number of disks
+    }
+    let entries = entries.min(u16::MAX as u64) as u16;
+    put_u32(&mut record, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+    put_u16(&mut record, 0); // Disk number
+    put_u16(&mut record, 0); // Disk with the central directory
+    put_u16(&mut record, entries);
+    put_u16(&mut record, entries);
+    put_u32(&mut record, size.min(u32::MAX as u64) as u32);
+    put_u32(&mut record, offset.min(u32::MAX as u64) as u32);
+    put_u16(&mut record, 0); // Comment length
+    record
+}
+
+fn zip64_extra_field(values: &[u64]) -> Vec<u8> {
+    let mut extra = Vec::with_capacity(4 + values.len() * 8);
+    put_u16(&mut extra, ZIP64_EXTRA_FIELD_ID);
+    put_u16(&mut extra, (values.len() * 8) as u16);
+    for value in values {
+        put_u64(&mut extra, *value);
+    }
+    extra
+}
+
+/// Values that don't fit into the header are moved to the zip64 extra field.
+fn clamp_to_u32(value: u64, zip64_values: &mut Vec<u64>) -> u32 {
+    if value >= u32::MAX as u64 {
+        zip64_values.push(value);
+        u32::MAX
+    } else {
+        value as u32
+    }
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Zip files always use `/` as separator.
+fn entry_name(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn mode(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt as _;
+    metadata.mode()
+}
+
+#[cfg(not(unix))]
+fn mode(metadata: &Metadata) -> u32 {
+    if metadata.is_dir() {
+        0o040755
+    } else {
+        0o100644
+    }
+}
+
+/// Converts a timestamp to the MS-DOS format used by Zip files as `(time, date)`.
+fn dos_date_time(time: SystemTime) -> (u16, u16) {
+    const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+    let seconds_of_day = seconds % SECONDS_PER_DAY;
+
+    // MS-DOS dates can only represent the years 1980 to 2107.
+    if year < 1980 {
+        return (0, 1 << 5 | 1);
+    }
+    let year = year.min(2107);
+
+    let time =
+        (seconds_of_day / 3600) << 11 | (seconds_of_day / 60 % 60) << 5 | (seconds_of_day % 60 / 2);
+    let date = (year - 1980) << 9 | month << 5 | day;
+    (time as u16, date as u16)
+}
+
+/// Converts days since the Unix epoch to `(year, month, day)`.
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The size of a file that is deflated into uncompressed blocks.
+/// Even an empty file needs a (final) block.
+fn deflated_size(size: u64) -> u64 {
+    let blocks = size.div_ceil(MAX_BLOCK_SIZE as u64).max(1);
+    size + blocks * BLOCK_HEADER_SIZE
+}
+
+/// Deflates exactly `size` bytes from the file into uncompressed blocks and returns their checksum.
+fn deflate_file(
+    path: &Path,
+    size: u64,
+    writer: &mut impl Write,
+    cancellation: &CancellationToken,
+) -> Result<u32, PortalError> {
+    let mut reader = File::open(path)?;
+    let mut hasher = Hasher::new();
+    let mut buffer = vec![0; MAX_BLOCK_SIZE];
+    let mut remaining = size;
+    loop {
+        cancellation.error_if_canceled()?;
+        let block_size = MAX_BLOCK_SIZE.min(remaining as usize);
+        match reader.read_exact(&mut buffer[..block_size]) {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(file_changed(path))
+            }
+            result => result?,
+        }
+        remaining -= block_size as u64;
+        hasher.update(&buffer[..block_size]);
+        writer.write_all(&block_header(block_size as u16, remaining == 0))?;
+        writer.write_all(&buffer[..block_size])?;
+        if remaining == 0 {
+            break;
+        }
+    }
+    if reader.read(&mut buffer[..1])? != 0 {
+        return Err(file_changed(path));
+    }
+    Ok(hasher.finalize())
+}
+
+/// The header of an uncompressed deflate block, which starts byte-aligned
+/// as every block before it is uncompressed as well.
+fn block_header(len: u16, is_final: bool) -> [u8; BLOCK_HEADER_SIZE as usize] {
+    let [len_low, len_high] = len.to_le_bytes();
+    let [nlen_low, nlen_high] = (!len).to_le_bytes();
+    [u8::from(is_final), len_low, len_high, nlen_low, nlen_high]
+}
+
+fn file_changed(path: &Path) -> PortalError {
+    io::Error::other(format!(
+        "The file \"{}\" was changed while it was being sent",
+        path.display()
+    ))
+    .into()
+}
+
+/// Sends the written bytes in chunks to a channel, blocking while the channel is full.
+struct ChannelWriter {
+    sender: mpsc::Sender<io::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn new(sender: mpsc::Sender<io::Result<Vec<u8>>>) -> Self {
+        ChannelWriter {
+            sender,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    fn send(&mut self, chunk: io::Result<Vec<u8>>) -> bool {
+        block_on(self.sender.send(chunk)).is_ok()
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_SIZE));
+        if self.send(Ok(chunk)) {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancellation::CancellationSource;
+    use futures::AsyncReadExt;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+    use zip::ZipArchive;
+
+    #[test]
+    fn packed_archive_has_precomputed_size() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let folder = temp_dir.path().join("folder");
+        fs::create_dir_all(folder.join("sub")).expect("folder to be created");
+        fs::write(folder.join("a.txt"), "a").expect("file to be written");
+        fs::write(folder.join("sub").join("b.txt"), "bb").expect("file to be written");
+
+        let mut last_progress = None;
+        let zip = prepare_folder_zip(
+            &folder,
+            |progress| last_progress = Some(progress),
+            cancellation_token(),
+        )
+        .expect("scan to succeed");
+        let last_progress = last_progress.expect("progress to be reported");
+        assert_eq!(last_progress.files, Progress { value: 2, total: 2 });
+        assert_eq!(last_progress.bytes, Progress { value: 3, total: 3 });
+        let bytes = pack(zip.into());
+
+        let mut archive = ZipArchive::new(Cursor::new(&bytes)).expect("archive to be valid");
+        let mut names = archive.file_names().map(str::to_owned).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.txt", "sub/", "sub/b.txt"]);
+        let mut contents = String::new();
+        archive
+            .by_name("sub/b.txt")
+            .expect("entry to exist")
+            .read_to_string(&mut contents)
+            .expect("entry to be readable");
+        assert_eq!(contents, "bb");
+    }
+
+    #[test]
+    fn selection_entries_are_named_after_the_selected_paths() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let file = temp_dir.path().join("file.txt");
+        let folder = temp_dir.path().join("folder");
+        fs::write(&file, "file").expect("file to be written");
+        fs::create_dir(&folder).expect("folder to be created");
+        fs::write(folder.join("nested.txt"), "nested").expect("file to be written");
+
+        let zip = prepare_selection_zip(&[file, folder], |_| {}, cancellation_token())
+            .expect("scan to succeed");
+        let bytes = pack(zip.into());
+
+        let archive = ZipArchive::new(Cursor::new(&bytes)).expect("archive to be valid");
+        let mut names = archive.file_names().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["file.txt", "folder/", "folder/nested.txt"]);
+    }
+
+    #[test]
+    fn reading_fails_if_a_file_changed() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let file = temp_dir.path().join("file.txt");
+        fs::write(&file, "before").expect("file to be written");
+        let zip = Arc::new(
+            prepare_selection_zip(std::slice::from_ref(&file), |_| {}, cancellation_token())
+                .expect("scan to succeed"),
+        );
+        fs::write(&file, "after the change").expect("file to be written");
+
+        let mut bytes = Vec::new();
+        let result = block_on(zip.reader(cancellation_token()).read_to_end(&mut bytes));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn files_are_deflated_into_blocks() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let folder = temp_dir.path().join("folder");
+        fs::create_dir(&folder).expect("folder to be created");
+        let contents = (0..MAX_BLOCK_SIZE * 2 + 10)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        fs::write(folder.join("large.bin"), &contents).expect("file to be written");
+        fs::write(folder.join("empty.bin"), "").expect("file to be written");
+
+        let zip =
+            prepare_folder_zip(&folder, |_| {}, cancellation_token()).expect("scan to succeed");
+        let bytes = pack(zip.into());
+
+        let mut archive = ZipArchive::new(Cursor::new(&bytes)).expect("archive to be valid");
+        let mut large = archive.by_name("large.bin").expect("entry to exist");
+        assert_eq!(large.compression(), zip::CompressionMethod::Deflated);
+        assert_eq!(
+            large.compressed_size(),
+            contents.len() as u64 + 3 * BLOCK_HEADER_SIZE
+        );
+        let mut unpacked = Vec::new();
+        large
+            .read_to_end(&mut unpacked)
+            .expect("entry to be readable");
+        assert_eq!(unpacked, contents);
+        drop(large);
+        let mut empty = archive.by_name("empty.bin").expect("entry to exist");
+        let mut unpacked = Vec::new();
+        empty
+            .read_to_end(&mut unpacked)
+            .expect("entry to be readable");
+        assert!(unpacked.is_empty());
+    }
+
+    #[test]
+    fn converts_timestamps_to_dos_format() {
+        // 2023-06-15 13:45:30 UTC
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1686836730);
+        assert_eq!(
+            dos_date_time(time),
+            (13 << 11 | 45 << 5 | 15, 43 << 9 | 6 << 5 | 15)
+        );
+        assert_eq!(dos_date_time(UNIX_EPOCH), (0, 1 << 5 | 1));
+    }
+
+    fn pack(zip: Arc<StreamingZip>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        block_on(zip.reader(cancellation_token()).read_to_end(&mut bytes))
+            .expect("packing to succeed");
+        assert_eq!(bytes.len() as u64, zip.size());
+        bytes
+    }
+
+    fn cancellation_token() -> CancellationToken {
+        CancellationSource::default().token()
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt;
+use ubyte::{ByteUnit, ToByteUnit};
+
+pub(crate) struct ByteDisplay(pub(crate) ByteUnit);
+
+// Same as https://github.com/SergioBenitez/ubyte/blob/master/src/byte_unit.rs#L442
+// except with a space between value and suffix.
+impl fmt::Display for ByteDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NO_BREAK_SPACE: &str = "\u{00A0}";
+        let (whole, rem, suffix, unit) = self.0.repr();
+        let width = f.width().unwrap_or(0);
+        if rem != 0f64 && f.precision().map(|p| p > 0).unwrap_or(true) {
+            let p = f.precision().unwrap_or(2);
+            let k = 10u64.saturating_pow(p as u32) as f64;
+            write!(
+                f,
+                "{:0width$}.{:0p$.0}{NO_BREAK_SPACE}{}",
+                whole,
+                rem * k,
+                suffix,
+            )
+        } else if rem > 0.5f64 {
+            ((whole.bytes() + 1) * unit).fmt(f)
+        } else {
+            write!(f, "{whole:0width$}{NO_BREAK_SPACE}{suffix}")
+        }
+    }
+}
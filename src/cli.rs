@@ -0,0 +1,447 @@
+//! Headless mode for transferring files without opening a window,
+//! e.g. for scripting transfers on servers without a display.
+
+use self::event::{Event, Output};
+use async_std::io;
+use async_std::task;
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Subcommand};
+use futures::future::Fuse;
+use futures::{select, FutureExt};
+use portal_wormhole::receive::{
+    connect, ReceiveOffer, ReceiveOptions, ReceiveRequestController, ReceiveTarget,
+    ReceiveTextController,
+};
+use portal_wormhole::send::{
+    send, SendCode, SendOptions, SendRequest, SendingController, SendingProgress,
+    DEFAULT_WORD_COUNT,
+};
+use portal_wormhole::{normalize_code, Code, ConnectionSettings, ContentHash, PortalError};
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use url::Url;
+
+mod event;
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Send files, folders or a text message without opening a window.
+    Send {
+        /// The files or folders to send.
+        #[arg(required_unless_present = "text")]
+        paths: Vec<PathBuf>,
+        /// Send a text message instead of files.
+        #[arg(long, conflicts_with = "paths")]
+        text: Option<String>,
+        /// Show the share link as a QR code, e.g. for scanning it with a phone.
+        #[arg(long)]
+        qr: bool,
+        /// Number of words in the generated code.
+        #[arg(long, value_name = "WORDS", default_value_t = DEFAULT_WORD_COUNT, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        code_length: usize,
+        /// Use this code (e.g. agreed on beforehand) instead of generating one.
+        #[arg(long, conflicts_with = "code_length")]
+        code: Option<String>,
+        /// Ask to confirm that the receiver sees the same verification symbols before sending.
+        #[arg(long)]
+        verify: bool,
+        #[command(flatten)]
+        connection: ConnectionArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Receive a file or text message without opening a window.
+    Receive {
+        /// The transmit code from the sender.
+        code: String,
+        /// Accept the offered file without asking for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+        /// The folder to save the file to, defaults to the Downloads folder.
+        #[arg(short, long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+        /// Extract received folders and Zip archives after receiving them.
+        #[arg(long)]
+        extract: bool,
+        /// Continue an interrupted transfer of the same file instead of starting over.
+        #[arg(long)]
+        resume: bool,
+        /// Ask to confirm that the sender sees the same verification symbols before accepting,
+        /// even with --yes.
+        #[arg(long)]
+        verify: bool,
+        #[command(flatten)]
+        connection: ConnectionArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct ConnectionArgs {
+    /// URL of the rendezvous (mailbox) server.
+    #[arg(long, value_name = "URL")]
+    rendezvous_server: Option<Url>,
+    /// App ID used on the rendezvous server.
+    #[arg(long)]
+    app_id: Option<String>,
+    /// URL of a relay server, can be specified multiple times.
+    #[arg(long = "relay-server", value_name = "URL")]
+    relay_servers: Vec<Url>,
+}
+
+impl From<ConnectionArgs> for ConnectionSettings {
+    fn from(value: ConnectionArgs) -> Self {
+        let defaults = ConnectionSettings::default();
+        ConnectionSettings {
+            rendezvous_url: value.rendezvous_server.unwrap_or(defaults.rendezvous_url),
+            app_id: value.app_id.unwrap_or(defaults.app_id),
+            relay_servers: if value.relay_servers.is_empty() {
+                defaults.relay_servers
+            } else {
+                value.relay_servers
+            },
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+    /// Print progress as newline-delimited JSON events to stdout.
+    #[arg(long)]
+    json: bool,
+}
+
+/// Exit codes of the headless mode.
+///
+/// * `0`: The transfer was successful.
+/// * `1`: The transfer failed.
+/// * `2`: The arguments are invalid.
+/// * `3`: The transfer was rejected (by either side).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Status {
+    Success = 0,
+    Failure = 1,
+    Usage = 2,
+    Rejected = 3,
+}
+
+impl From<Status> for ExitCode {
+    fn from(value: Status) -> Self {
+        ExitCode::from(value as u8)
+    }
+}
+
+impl From<&PortalError> for Status {
+    fn from(value: &PortalError) -> Self {
+        match value {
+            PortalError::TransferRejected(_) => Status::Rejected,
+            PortalError::InvalidRelayServer(_) => Status::Usage,
+            _ => Status::Failure,
+        }
+    }
+}
+
+pub async fn run(command: Command) -> ExitCode {
+    match command {
+        Command::Send {
+            paths,
+            text,
+            qr,
+            code_length,
+            code,
+            verify,
+            connection,
+            output,
+        } => {
+            let connection_settings = ConnectionSettings::from(connection);
+            let mut output = Output::new(output.json);
+            if let Err(error) = connection_settings.validate() {
+                return usage_error(&mut output, error).into();
+            }
+            if qr {
+                output = output.with_qr_code(connection_settings.rendezvous_url.clone());
+            }
+            let code = match code {
+                Some(code) => match normalize_code(&code).parse::<Code>() {
+                    Ok(code) => SendCode::Custom(code),
+                    Err(error) => return usage_error(&mut output, error).into(),
+                },
+                None => SendCode::Generated {
+                    word_count: code_length,
+                },
+            };
+            let options = SendOptions {
+                code,
+                require_verification: verify,
+            };
+            run_send(paths, text, options, connection_settings, output).await
+        }
+        Command::Receive {
+            code,
+            yes,
+            output_dir,
+            extract,
+            resume,
+            verify,
+            connection,
+            output,
+        } => {
+            let connection_settings = ConnectionSettings::from(connection);
+            let mut output = Output::new(output.json);
+            if let Err(error) = connection_settings.validate() {
+                return usage_error(&mut output, error).into();
+            }
+            let options = ReceiveOptions {
+                target: output_dir.map_or(ReceiveTarget::Downloads, ReceiveTarget::Directory),
+                extract_archives: extract,
+                resume,
+            };
+            run_receive(code, yes, verify, options, connection_settings, output).await
+        }
+    }
+    .into()
+}
+
+async fn run_send(
+    paths: Vec<PathBuf>,
+    text: Option<String>,
+    options: SendOptions,
+    connection_settings: ConnectionSettings,
+    mut output: Output,
+) -> Status {
+    let send_request = match text {
+        Some(text) => SendRequest::Text(text),
+        None => match send_request_from_paths(&paths) {
+            Ok(Some(send_request)) => send_request,
+            Ok(None) => return Status::Usage,
+            Err(error) => return usage_error(&mut output, error),
+        },
+    };
+
+    let (future, mut controller) = send(send_request, connection_settings, options, || {});
+    let mut verification = None;
+    let result = run_with_ticks(future, || {
+        report_sending(&mut output, &mut controller);
+        confirm_verification(&mut controller, &mut verification);
+    })
+    .await;
+    report_sending(&mut output, &mut controller);
+
+    match result {
+        Ok(()) => success(&mut output, None, controller.content_hash()),
+        Err((error, _)) => failure(&mut output, &error),
+    }
+}
+
+fn send_request_from_paths(paths: &[PathBuf]) -> std::io::Result<Option<SendRequest>> {
+    let paths = paths
+        .iter()
+        .map(std::fs::canonicalize)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(SendRequest::from_paths(paths))
+}
+
+fn report_sending(output: &mut Output, controller: &mut SendingController) {
+    let event = match controller.progress() {
+        SendingProgress::Packing(progress) => Event::Packing {
+            files: (&progress.files).into(),
+            bytes: (&progress.bytes).into(),
+            current_file: &progress.current_file,
+        },
+        SendingProgress::Connecting => Event::Connecting { code: None },
+        SendingProgress::Connected(code) => Event::Connected { code },
+        SendingProgress::Verifying(verifier) | SendingProgress::PreparingToSend(verifier) => {
+            Event::PreparingToSend {
+                verifier: verifier.into(),
+            }
+        }
+        SendingProgress::Sending(transit_info, progress, rate) => Event::Sending {
+            transit: transit_info.as_ref().into(),
+            progress: progress.into(),
+            rate: rate.into(),
+        },
+    };
+    output.emit(&event);
+}
+
+/// Asks whether the receiver sees the same symbols once the transfer waits for it,
+/// without blocking the ticks in the meantime.
+fn confirm_verification(
+    controller: &mut SendingController,
+    verification: &mut Option<Fuse<task::JoinHandle<bool>>>,
+) {
+    if !matches!(controller.progress(), SendingProgress::Verifying(_)) {
+        return;
+    }
+    let answer =
+        verification.get_or_insert_with(|| task::spawn(confirm(VERIFICATION_QUESTION)).fuse());
+    match answer.now_or_never() {
+        Some(true) => controller.confirm_verification(),
+        Some(false) => controller.cancel(),
+        None => {}
+    }
+}
+
+async fn run_receive(
+    code: String,
+    yes: bool,
+    verify: bool,
+    options: ReceiveOptions,
+    connection_settings: ConnectionSettings,
+    mut output: Output,
+) -> Status {
+    let code = match normalize_code(&code).parse::<Code>() {
+        Ok(code) => code,
+        Err(error) => return usage_error(&mut output, error),
+    };
+
+    output.emit(&Event::Connecting { code: Some(&code) });
+    let (future, _controller) = connect(code, connection_settings);
+    let receive_request = match future.await {
+        Ok(ReceiveOffer::File(receive_request)) => receive_request,
+        Ok(ReceiveOffer::Text(text_offer)) => {
+            return receive_text(text_offer, verify, output).await
+        }
+        Err(error) => return failure(&mut output, &error),
+    };
+
+    output.emit(&Event::Offer {
+        file_name: &receive_request.file_name(),
+        file_size: receive_request.filesize(),
+        directory: receive_request.is_directory(),
+        verifier: receive_request.verifier().into(),
+    });
+
+    if verify && !confirm(VERIFICATION_QUESTION).await {
+        return rejected(&mut output, receive_request.reject().await);
+    }
+
+    let question = if receive_request.is_directory() {
+        "Do you want to download this folder?"
+    } else {
+        "Do you want to download this file?"
+    };
+    if !yes && !confirm(question).await {
+        return rejected(&mut output, receive_request.reject().await);
+    }
+
+    receive(receive_request, options, output).await
+}
+
+/// The message is only acknowledged (and printed) once the verifier has been confirmed.
+async fn receive_text(
+    text_offer: ReceiveTextController,
+    verify: bool,
+    mut output: Output,
+) -> Status {
+    output.emit(&Event::TextOffer {
+        verifier: text_offer.verifier().into(),
+    });
+    if verify && !confirm(VERIFICATION_QUESTION).await {
+        return rejected(&mut output, text_offer.reject().await);
+    }
+
+    match text_offer.accept().await {
+        Ok(text) => {
+            output.emit(&Event::Text { text: &text });
+            Status::Success
+        }
+        Err(error) => failure(&mut output, &error),
+    }
+}
+
+fn rejected(output: &mut Output, result: Result<(), PortalError>) -> Status {
+    match result {
+        Ok(()) => {
+            output.emit(&Event::Error {
+                category: "rejected",
+                message: "Transfer rejected".to_owned(),
+            });
+            Status::Rejected
+        }
+        Err(error) => failure(output, &error),
+    }
+}
+
+async fn receive(
+    receive_request: ReceiveRequestController,
+    options: ReceiveOptions,
+    mut output: Output,
+) -> Status {
+    let (future, mut controller) = receive_request.accept(options, || {});
+    let result = run_with_ticks(future, || {
+        let progress = *controller.progress();
+        let rate = *controller.rate();
+        if let Some(extraction_progress) = controller.extraction_progress() {
+            output.emit(&Event::Extracting {
+                progress: extraction_progress.into(),
+            });
+        } else if let Some(transit_info) = controller.transit_info() {
+            output.emit(&Event::Receiving {
+                transit: transit_info.into(),
+                progress: (&progress).into(),
+                rate: (&rate).into(),
+            });
+        }
+    })
+    .await;
+
+    match result {
+        Ok(path) => success(&mut output, Some(path), controller.content_hash()),
+        Err(error) => failure(&mut output, &error),
+    }
+}
+
+/// Drives the future to completion while calling `on_tick` periodically.
+async fn run_with_ticks<T>(
+    future: impl Future<Output = T> + Send + 'static,
+    mut on_tick: impl FnMut(),
+) -> T
+where
+    T: Send + 'static,
+{
+    const TICK: Duration = Duration::from_millis(100);
+    let mut task = task::spawn(future).fuse();
+    loop {
+        select! {
+            result = task => return result,
+            _ = task::sleep(TICK).fuse() => on_tick(),
+        }
+    }
+}
+
+const VERIFICATION_QUESTION: &str = "Does your peer see the same verification symbols?";
+
+async fn confirm(question: &str) -> bool {
+    eprint!("{question} [y/N] ");
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer).await {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+fn success(
+    output: &mut Output,
+    path: Option<PathBuf>,
+    content_hash: Option<&ContentHash>,
+) -> Status {
+    output.emit(&Event::Success {
+        path: path.as_deref(),
+        content_hash,
+    });
+    Status::Success
+}
+
+fn failure(output: &mut Output, error: &PortalError) -> Status {
+    output.emit(&Event::error(error));
+    Status::from(error)
+}
+
+fn usage_error(output: &mut Output, error: impl ToString) -> Status {
+    output.emit(&Event::usage_error(error));
+    Status::Usage
+}
@@ -0,0 +1,425 @@
+use crate::byte_display::ByteDisplay;
+use crate::progress_display::DurationDisplay;
+use crate::transit_info::TransitInfoDisplay;
+use portal_wormhole::{
+    share_link, Code, ConnectionType, ContentHash, PortalError, Progress, TransferRate,
+    TransitInfo, Verifier,
+};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use serde::{Serialize, Serializer};
+use std::io::{IsTerminal, Write as _};
+use std::net::SocketAddr;
+use std::path::Path;
+use ubyte::ToByteUnit;
+use url::Url;
+
+/// Everything that happens during a transfer is reported as an event,
+/// which is either printed for humans or as newline-delimited JSON.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(super) enum Event<'a> {
+    Packing {
+        files: ProgressEvent,
+        bytes: ProgressEvent,
+        current_file: &'a Path,
+    },
+    Connecting {
+        #[serde(
+            skip_serializing_if = "Option::is_none",
+            serialize_with = "serialize_optional_code"
+        )]
+        code: Option<&'a Code>,
+    },
+    Connected {
+        #[serde(serialize_with = "serialize_code")]
+        code: &'a Code,
+    },
+    PreparingToSend {
+        verifier: VerifierEvent,
+    },
+    Sending {
+        transit: TransitEvent<'a>,
+        progress: ProgressEvent,
+        rate: RateEvent,
+    },
+    Offer {
+        file_name: &'a str,
+        file_size: u64,
+        directory: bool,
+        verifier: VerifierEvent,
+    },
+    Receiving {
+        transit: TransitEvent<'a>,
+        progress: ProgressEvent,
+        rate: RateEvent,
+    },
+    Extracting {
+        progress: ProgressEvent,
+    },
+    TextOffer {
+        verifier: VerifierEvent,
+    },
+    Text {
+        text: &'a str,
+    },
+    Success {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<&'a Path>,
+        #[serde(flatten)]
+        content_hash: Option<&'a ContentHash>,
+    },
+    Error {
+        category: &'static str,
+        message: String,
+    },
+}
+
+impl<'a> Event<'a> {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Packing { .. } => "packing",
+            Event::Connecting { .. } => "connecting",
+            Event::Connected { .. } => "connected",
+            Event::PreparingToSend { .. } => "preparing_to_send",
+            Event::Sending { .. } => "sending",
+            Event::Offer { .. } => "offer",
+            Event::Receiving { .. } => "receiving",
+            Event::Extracting { .. } => "extracting",
+            Event::TextOffer { .. } => "text_offer",
+            Event::Text { .. } => "text",
+            Event::Success { .. } => "success",
+            Event::Error { .. } => "error",
+        }
+    }
+
+    pub(super) fn error(error: &PortalError) -> Self {
+        Event::Error {
+            category: error.category(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Errors that happen before a transfer is started, e.g. invalid arguments.
+    pub(super) fn usage_error(error: impl ToString) -> Self {
+        Event::Error {
+            category: "usage",
+            message: error.to_string(),
+        }
+    }
+}
+
+fn serialize_code<S: Serializer>(code: &&Code, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(code)
+}
+
+fn serialize_optional_code<S: Serializer>(
+    code: &Option<&Code>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match code {
+        Some(code) => serializer.collect_str(code),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(super) struct TransitEvent<'a> {
+    #[serde(rename = "type")]
+    connection_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relay: Option<&'a str>,
+    peer_address: SocketAddr,
+    #[serde(skip)]
+    info: &'a TransitInfo,
+}
+
+impl<'a> From<&'a TransitInfo> for TransitEvent<'a> {
+    fn from(info: &'a TransitInfo) -> Self {
+        let (connection_type, relay) = match &info.conn_type {
+            ConnectionType::Direct => ("direct", None),
+            ConnectionType::Relay { name } => ("relay", name.as_deref()),
+            _ => ("unknown", None),
+        };
+        TransitEvent {
+            connection_type,
+            relay,
+            peer_address: info.peer_addr,
+            info,
+        }
+    }
+}
+
+/// Both peers see the same verifier if they are connected to each other.
+#[derive(Serialize, Debug)]
+pub(super) struct VerifierEvent {
+    emoji: String,
+    words: String,
+}
+
+impl From<&Verifier> for VerifierEvent {
+    fn from(verifier: &Verifier) -> Self {
+        VerifierEvent {
+            emoji: verifier.emoji(),
+            words: verifier.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub(super) struct ProgressEvent {
+    value: u64,
+    total: u64,
+}
+
+impl From<&Progress> for ProgressEvent {
+    fn from(value: &Progress) -> Self {
+        ProgressEvent {
+            value: value.value,
+            total: value.total,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub(super) struct RateEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes_per_second: Option<u64>,
+    elapsed_seconds: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining_seconds: Option<u64>,
+    #[serde(skip)]
+    rate: TransferRate,
+}
+
+impl From<&TransferRate> for RateEvent {
+    fn from(rate: &TransferRate) -> Self {
+        RateEvent {
+            bytes_per_second: rate.bytes_per_second,
+            elapsed_seconds: rate.elapsed.as_secs(),
+            remaining_seconds: rate.remaining.map(|remaining| remaining.as_secs()),
+            rate: *rate,
+        }
+    }
+}
+
+pub(super) enum Output {
+    Human {
+        last_event: Option<&'static str>,
+        progress_visible: bool,
+        /// The rendezvous server that the QR code is printed for, if it's printed.
+        qr_code: Option<Url>,
+    },
+    Json {
+        last_line: String,
+    },
+}
+
+impl Output {
+    pub(super) fn new(json: bool) -> Self {
+        if json {
+            Output::Json {
+                last_line: String::new(),
+            }
+        } else {
+            Output::Human {
+                last_event: None,
+                progress_visible: false,
+                qr_code: None,
+            }
+        }
+    }
+
+    /// Prints the share link as a QR code once the code is known (not in JSON mode).
+    pub(super) fn with_qr_code(mut self, rendezvous_url: Url) -> Self {
+        if let Output::Human { qr_code, .. } = &mut self {
+            *qr_code = Some(rendezvous_url);
+        }
+        self
+    }
+
+    pub(super) fn emit(&mut self, event: &Event) {
+        match self {
+            Output::Human {
+                last_event,
+                progress_visible,
+                qr_code,
+            } => {
+                let is_new = *last_event != Some(event.name());
+                *last_event = Some(event.name());
+                print_human(event, is_new, progress_visible, qr_code.as_ref());
+            }
+            Output::Json { last_line } => {
+                let line = serde_json::to_string(event).expect("events should be serializable");
+                // Progress is polled, so we skip events that have not changed.
+                if *last_line != line {
+                    println!("{line}");
+                    *last_line = line;
+                }
+            }
+        }
+    }
+}
+
+fn print_human(event: &Event, is_new: bool, progress_visible: &mut bool, qr_code: Option<&Url>) {
+    if *progress_visible && is_new {
+        eprintln!();
+        *progress_visible = false;
+    }
+
+    match event {
+        Event::Packing {
+            files,
+            bytes,
+            current_file: _,
+        } => {
+            if is_new {
+                eprintln!("Packing files to a Zip file...");
+            }
+            *progress_visible |= print_file_count(files, bytes);
+        }
+        Event::Connecting { code: None } if is_new => eprintln!("Generating transmit code..."),
+        Event::Connecting { code: Some(code) } if is_new => {
+            eprintln!("Connecting with peer using transfer code \"{code}\"...")
+        }
+        Event::Connected { code } if is_new => {
+            println!("{code}");
+            eprintln!("On the other computer, enter the code above or run: portal receive {code}");
+            if let Some(rendezvous_url) = qr_code {
+                print_qr_code(code, rendezvous_url);
+            }
+        }
+        Event::PreparingToSend { verifier } if is_new => {
+            eprintln!("Connected to peer");
+            print_verifier(verifier);
+        }
+        Event::Sending {
+            transit,
+            progress,
+            rate,
+        }
+        | Event::Receiving {
+            transit,
+            progress,
+            rate,
+        } => {
+            if is_new {
+                let verb = match event {
+                    Event::Sending { .. } => "Sending",
+                    _ => "Receiving",
+                };
+                eprintln!("{verb}{}", TransitInfoDisplay(transit.info));
+            }
+            *progress_visible |= print_progress(progress, Some(&rate.rate));
+        }
+        Event::Offer {
+            file_name,
+            file_size,
+            directory,
+            verifier,
+        } => {
+            eprintln!(
+                "Your peer wants to send you the {} \"{file_name}\" (Size: {}).",
+                if *directory { "folder" } else { "file" },
+                ByteDisplay(file_size.bytes())
+            );
+            print_verifier(verifier);
+        }
+        Event::Extracting { progress } => {
+            if is_new {
+                eprintln!("Extracting archive...");
+            }
+            *progress_visible |= print_progress(progress, None);
+        }
+        Event::TextOffer { verifier } => {
+            eprintln!("Your peer wants to send you a text message.");
+            print_verifier(verifier);
+        }
+        Event::Text { text } => println!("{text}"),
+        Event::Success { path, content_hash } => {
+            if let Some(content_hash) = content_hash {
+                print_content_hash(content_hash);
+            }
+            match path {
+                Some(path) => println!("{}", path.display()),
+                None => eprintln!("Transfer successful"),
+            }
+        }
+        Event::Error { message, .. } => eprintln!("Error: {message}"),
+        _ => {}
+    }
+}
+
+/// The colors are inverted, so that the code is readable on dark terminals.
+fn print_qr_code(code: &Code, rendezvous_url: &Url) {
+    let link = share_link(code.clone(), rendezvous_url);
+    if let Ok(qr_code) = QrCode::new(&link) {
+        let rendered = qr_code
+            .render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .build();
+        eprintln!("{rendered}");
+        eprintln!("Or scan the QR code above to open {link}");
+    }
+}
+
+fn print_verifier(verifier: &VerifierEvent) {
+    eprintln!("Verification: {} ({})", verifier.emoji, verifier.words);
+}
+
+fn print_content_hash(content_hash: &ContentHash) {
+    let verified = if content_hash.verified {
+        "verified"
+    } else {
+        "not verified, the sender doesn't support it"
+    };
+    eprintln!("SHA-256: {} ({verified})", content_hash.sha256);
+}
+
+/// Returns `true` if the file count has been drawn.
+fn print_file_count(files: &ProgressEvent, bytes: &ProgressEvent) -> bool {
+    let stderr = std::io::stderr();
+    if !stderr.is_terminal() || files.total == 0 {
+        return false;
+    }
+
+    _ = write!(
+        stderr.lock(),
+        "\r{} of {} files ({} of {})",
+        files.value,
+        files.total,
+        ByteDisplay(bytes.value.bytes()),
+        ByteDisplay(bytes.total.bytes()),
+    );
+    true
+}
+
+/// Returns `true` if the progress bar has been drawn.
+fn print_progress(progress: &ProgressEvent, rate: Option<&TransferRate>) -> bool {
+    const WIDTH: u64 = 30;
+
+    let stderr = std::io::stderr();
+    if !stderr.is_terminal() || progress.total == 0 {
+        return false;
+    }
+
+    let filled = (progress.value * WIDTH / progress.total).min(WIDTH) as usize;
+    let percent = progress.value * 100 / progress.total;
+    let mut line = format!(
+        "\r[{}{}] {percent:>3}% {} of {}",
+        "#".repeat(filled),
+        ".".repeat(WIDTH as usize - filled),
+        ByteDisplay(progress.value.bytes()),
+        ByteDisplay(progress.total.bytes()),
+    );
+    if let Some(bytes_per_second) = rate.and_then(|rate| rate.bytes_per_second) {
+        line += &format!(" · {:.1}/s", ByteDisplay(bytes_per_second.bytes()));
+    }
+    if let Some(remaining) = rate.and_then(|rate| rate.remaining) {
+        line += &format!(" · {} left", DurationDisplay(remaining));
+    }
+    // Clears what's left of a previous, longer line.
+    _ = write!(stderr.lock(), "{line}\x1b[K");
+    true
+}
@@ -45,7 +45,13 @@ pub fn font_definitions() -> FontDefinitions {
     );
     fonts.families.insert(
         FontFamily::Proportional,
-        vec![INTER_MEDIUM.to_owned(), LUCIDE_FONT_NAME.to_owned()],
+        vec![
+            INTER_MEDIUM.to_owned(),
+            LUCIDE_FONT_NAME.to_owned(),
+            // egui's default emoji fonts, used e.g. for the verifier.
+            "NotoEmoji-Regular".to_owned(),
+            "emoji-icon-font".to_owned(),
+        ],
     );
     fonts
         .families
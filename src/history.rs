@@ -0,0 +1,314 @@
+use crate::byte_display::ByteDisplay;
+use crate::font::{ICON_DOWNLOAD, ICON_UPLOAD};
+use crate::progress_display::DurationDisplay;
+use crate::widgets;
+use egui::{Context, Id, RichText, ScrollArea, Ui};
+use opener::{open, reveal};
+use portal_wormhole::{ConnectionType, ContentHash, PortalError, TransitInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use ubyte::ToByteUnit;
+
+/// Older transfers are forgotten so that the history stays cheap to persist.
+const MAX_TRANSFERS: usize = 100;
+
+/// Past file transfers, newest first, persisted across restarts using egui's memory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct History {
+    transfers: VecDeque<Transfer>,
+}
+
+impl History {
+    fn load(ctx: &Context) -> Self {
+        ctx.memory_mut(|m| m.data.get_persisted::<History>(history_id()))
+            .unwrap_or_default()
+    }
+
+    fn record(ctx: &Context, transfer: Transfer) {
+        ctx.memory_mut(|m| {
+            let history = m.data.get_persisted_mut_or_default::<History>(history_id());
+            history.transfers.push_front(transfer);
+            history.transfers.truncate(MAX_TRANSFERS);
+        });
+    }
+
+    fn clear(ctx: &Context) {
+        ctx.memory_mut(|m| m.data.insert_persisted(history_id(), History::default()));
+    }
+}
+
+fn history_id() -> Id {
+    Id::new("history")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Transfer {
+    direction: Direction,
+    name: String,
+    size: Option<u64>,
+    connection: Option<Connection>,
+    finished: SystemTime,
+    duration: Duration,
+    outcome: Outcome,
+    /// Where a received file has been saved.
+    path: Option<PathBuf>,
+    #[serde(default)]
+    content_hash: Option<ContentHash>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Connection {
+    Direct,
+    Relay(Option<String>),
+}
+
+impl Connection {
+    fn from_transit_info(transit_info: &TransitInfo) -> Option<Self> {
+        match &transit_info.conn_type {
+            ConnectionType::Direct => Some(Connection::Direct),
+            ConnectionType::Relay { name } => Some(Connection::Relay(name.clone())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum Outcome {
+    Completed,
+    Canceled,
+    Rejected,
+    Failed(String),
+}
+
+impl From<&PortalError> for Outcome {
+    fn from(error: &PortalError) -> Self {
+        match error {
+            PortalError::Canceled => Outcome::Canceled,
+            PortalError::TransferRejected(_) => Outcome::Rejected,
+            error => Outcome::Failed(error.to_string()),
+        }
+    }
+}
+
+/// Remembers what is being transferred and records it in the history once it's finished.
+pub(crate) struct TransferTracker {
+    direction: Direction,
+    name: String,
+}
+
+/// What is known about a transfer by the time it's finished.
+#[derive(Debug, Default)]
+pub(crate) struct TransferDetails<'a> {
+    pub(crate) transit_info: Option<&'a TransitInfo>,
+    pub(crate) size: Option<u64>,
+    /// How long the data has been transferred for, which doesn't include waiting for the peer.
+    pub(crate) duration: Duration,
+    /// Where a received file has been saved.
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) content_hash: Option<ContentHash>,
+}
+
+impl TransferTracker {
+    pub(crate) fn new(direction: Direction, name: impl Into<String>) -> Self {
+        TransferTracker {
+            direction,
+            name: name.into(),
+        }
+    }
+
+    pub(crate) fn finish(self, ctx: &Context, outcome: Outcome, details: TransferDetails<'_>) {
+        History::record(
+            ctx,
+            Transfer {
+                direction: self.direction,
+                name: self.name,
+                size: details.size,
+                connection: details.transit_info.and_then(Connection::from_transit_info),
+                finished: SystemTime::now(),
+                duration: details.duration,
+                outcome,
+                path: details.path,
+                content_hash: details.content_hash,
+            },
+        );
+    }
+}
+
+/// Returns `true` when the window should be closed.
+pub(crate) fn show_history_window(ctx: &Context) -> bool {
+    let mut open = true;
+    egui::Window::new("History")
+        .open(&mut open)
+        .collapsible(false)
+        .default_width(280.0)
+        .show(ctx, |ui| {
+            let history = History::load(ctx);
+            if history.transfers.is_empty() {
+                ui.label("Transfers will show up here once they're finished.");
+                return;
+            }
+
+            ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                for transfer in &history.transfers {
+                    show_transfer(ui, transfer);
+                    ui.separator();
+                }
+            });
+
+            if ui.button("Clear History").clicked() {
+                History::clear(ctx);
+            }
+        });
+    !open
+}
+
+fn show_transfer(ui: &mut Ui, transfer: &Transfer) {
+    let icon = match transfer.direction {
+        Direction::Sent => ICON_UPLOAD,
+        Direction::Received => ICON_DOWNLOAD,
+    };
+    ui.label(RichText::new(format!("{icon} {}", transfer.name)).strong());
+
+    let mut details = vec![format_age(transfer.finished)];
+    if let Some(size) = transfer.size {
+        details.push(ByteDisplay(size.bytes()).to_string());
+    }
+    match &transfer.connection {
+        Some(Connection::Direct) => details.push("direct".to_owned()),
+        Some(Connection::Relay(Some(relay))) => details.push(format!("relay \"{relay}\"")),
+        Some(Connection::Relay(None)) => details.push("relay".to_owned()),
+        None => {}
+    }
+    // Transfers that have been rejected or failed early never got to transfer any data.
+    if !transfer.duration.is_zero() {
+        details.push(DurationDisplay(transfer.duration).to_string());
+    }
+    ui.label(RichText::new(details.join(" · ")).weak());
+
+    match &transfer.outcome {
+        Outcome::Completed => {}
+        Outcome::Canceled => _ = ui.label("Canceled"),
+        Outcome::Rejected => _ = ui.label("Rejected"),
+        Outcome::Failed(error) => {
+            _ = ui.colored_label(ui.visuals().error_fg_color, format!("Failed: {error}"))
+        }
+    }
+
+    if let Some(content_hash) = &transfer.content_hash {
+        widgets::content_hash(ui, content_hash);
+    }
+
+    if let Some(path) = &transfer.path {
+        show_path_buttons(ui, path);
+    }
+}
+
+/// Whether the file still exists is only checked when it's opened,
+/// checking every transfer on every frame would be too much.
+fn show_path_buttons(ui: &mut Ui, path: &Path) {
+    let missing_id = Id::new("history-missing-path").with(path);
+    if ui.data(|d| d.get_temp::<bool>(missing_id).unwrap_or_default()) {
+        ui.weak("The file has been moved or deleted");
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        let open_clicked = ui.button("Open").clicked();
+        let reveal_clicked = ui.button("Show in Folder").clicked();
+        if (open_clicked || reveal_clicked) && !path.exists() {
+            ui.data_mut(|d| d.insert_temp(missing_id, true));
+        } else if open_clicked {
+            _ = open(path);
+        } else if reveal_clicked {
+            _ = reveal(path);
+        }
+    });
+}
+
+fn format_age(finished: SystemTime) -> String {
+    let age = SystemTime::now()
+        .duration_since(finished)
+        .unwrap_or_default()
+        .as_secs();
+    match age {
+        0..60 => "just now".to_owned(),
+        60..3600 => format!("{} min ago", age / 60),
+        3600..86400 => format!("{} h ago", age / 3600),
+        _ => format!("{} days ago", age / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Memory;
+
+    fn transfer(name: &str) -> Transfer {
+        Transfer {
+            direction: Direction::Received,
+            name: name.to_owned(),
+            size: Some(1024),
+            connection: Some(Connection::Direct),
+            finished: SystemTime::now(),
+            duration: Duration::from_secs(3),
+            outcome: Outcome::Completed,
+            path: Some(PathBuf::from("/downloads/file.bin")),
+            content_hash: None,
+        }
+    }
+
+    #[test]
+    fn forgets_the_oldest_transfers() {
+        let ctx = Context::default();
+        for index in 0..MAX_TRANSFERS + 5 {
+            History::record(&ctx, transfer(&index.to_string()));
+        }
+
+        let history = History::load(&ctx);
+        assert_eq!(history.transfers.len(), MAX_TRANSFERS);
+        let newest = history
+            .transfers
+            .front()
+            .expect("history to have transfers");
+        let oldest = history.transfers.back().expect("history to have transfers");
+        assert_eq!(newest.name, (MAX_TRANSFERS + 4).to_string());
+        assert_eq!(oldest.name, "5");
+    }
+
+    #[test]
+    fn survives_a_restart() {
+        let ctx = Context::default();
+        History::record(&ctx, transfer("file.bin"));
+        TransferTracker::new(Direction::Sent, "rejected.bin").finish(
+            &ctx,
+            Outcome::Rejected,
+            TransferDetails::default(),
+        );
+        let persisted = ctx
+            .memory(serde_json::to_string)
+            .expect("memory to be serialized");
+
+        let restarted = Context::default();
+        let memory: Memory = serde_json::from_str(&persisted).expect("memory to be deserialized");
+        restarted.memory_mut(|m| *m = memory);
+
+        let history = History::load(&restarted);
+        let names: Vec<_> = history.transfers.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["rejected.bin", "file.bin"]);
+        assert!(matches!(history.transfers[0].outcome, Outcome::Rejected));
+        assert_eq!(history.transfers[1].duration, Duration::from_secs(3));
+        assert_eq!(
+            history.transfers[1].path.as_deref(),
+            Some(Path::new("/downloads/file.bin"))
+        );
+    }
+}
@@ -1,16 +1,25 @@
 use egui::emath::Align;
-use egui::{self, Layout, Theme, Ui};
+use egui::{self, Layout, Theme, Ui, ViewportCommand};
 use egui_ext::ContextExt;
 use font::{font_definitions, ICON_X};
+use history::show_history_window;
 use main_view::{show_main_view, MainViewState};
 use poll_promise::Promise;
+use settings::{Settings, SettingsWindow};
+use single_instance::{ForwardedActions, PrimaryInstance};
+use std::collections::VecDeque;
 use std::error::Error;
+use std::mem;
+use transfers::Transfers;
 use version::{get_or_update_latest_app_version, AppVersion};
 use visuals::Accent;
-use widgets::{app_menu, cancel_button, page, CancelLabel};
+use widgets::{app_menu, cancel_button, page, AppMenuResponse, CancelLabel};
 
+mod byte_display;
+pub mod cli;
 mod egui_ext;
 mod font;
+mod history;
 mod receive;
 pub(crate) use receive::*;
 mod send;
@@ -19,6 +28,10 @@ mod startup_action;
 pub use startup_action::*;
 mod auto_viewport_theme;
 mod main_view;
+mod progress_display;
+mod settings;
+pub mod single_instance;
+mod transfers;
 mod transit_info;
 mod version;
 mod visuals;
@@ -27,11 +40,18 @@ mod widgets;
 pub struct PortalApp {
     state: PortalAppState,
     version: Promise<Option<AppVersion>>,
+    settings_window: Option<SettingsWindow>,
+    history_window_open: bool,
+    transfers: Transfers,
+    forwarded_actions: ForwardedActions,
+    /// Forwarded actions that wait for the view they're meant for to finish its transfer.
+    queued_actions: VecDeque<StartupAction>,
 }
 
 enum PortalAppState {
     Main(MainViewState),
-    UriError(Box<dyn Error>),
+    /// The main view is kept, so that its transfers continue once the error is dismissed.
+    UriError(Box<dyn Error>, MainViewState),
 }
 
 impl Default for PortalAppState {
@@ -43,15 +63,23 @@ impl Default for PortalAppState {
 impl From<StartupAction> for PortalAppState {
     fn from(value: StartupAction) -> Self {
         match value {
-            StartupAction::ShowInvalidUriError(error) => PortalAppState::UriError(error),
+            StartupAction::ShowInvalidUriError(error) => {
+                PortalAppState::UriError(error, MainViewState::default())
+            }
             StartupAction::None => Default::default(),
             StartupAction::ReceiveFile(action) => PortalAppState::Main(MainViewState::from(action)),
+            StartupAction::SendFiles(action) => PortalAppState::Main(MainViewState::from(action)),
+            StartupAction::SendToPeer(action) => PortalAppState::Main(MainViewState::from(action)),
         }
     }
 }
 
 impl PortalApp {
-    pub fn new(cc: &eframe::CreationContext, action: StartupAction) -> Self {
+    pub fn new(
+        cc: &eframe::CreationContext,
+        action: StartupAction,
+        instance: PrimaryInstance,
+    ) -> Self {
         cc.egui_ctx.set_fonts(font_definitions());
         auto_viewport_theme::register(&cc.egui_ctx);
 
@@ -60,34 +88,133 @@ impl PortalApp {
             version: cc
                 .egui_ctx
                 .spawn_async(get_or_update_latest_app_version(cc.egui_ctx.clone())),
+            settings_window: None,
+            history_window_open: false,
+            transfers: Transfers::default(),
+            forwarded_actions: instance.listen(cc.egui_ctx.clone()),
+            queued_actions: VecDeque::new(),
         }
     }
 }
 
 impl eframe::App for PortalApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        while let Some(action) = self.forwarded_actions.try_recv() {
+            ctx.send_viewport_cmd(ViewportCommand::Minimized(false));
+            ctx.send_viewport_cmd(ViewportCommand::Focus);
+            self.queued_actions.push_back(action);
+        }
+        self.open_queued();
+
         self.apply_accent(ctx);
 
-        app_menu(ctx, self.version.ready().cloned().flatten());
+        match app_menu(ctx, self.version.ready().cloned().flatten()) {
+            Some(AppMenuResponse::OpenSettings) => {
+                self.settings_window = Some(SettingsWindow::new(Settings::load(ctx)));
+            }
+            Some(AppMenuResponse::OpenHistory) => self.history_window_open = true,
+            None => {}
+        }
+
+        if let Some(settings_window) = &mut self.settings_window {
+            if settings_window.show(ctx) {
+                self.settings_window = None;
+            }
+        }
+
+        if self.history_window_open && show_history_window(ctx) {
+            self.history_window_open = false;
+        }
+
+        self.transfers.show(ctx);
 
-        egui::CentralPanel::default().show(ctx, |ui| {
+        let action = egui::CentralPanel::default().show(ctx, |ui| {
             ui.with_layout(Layout::top_down(Align::Center), |ui| {
+                if !self.queued_actions.is_empty() {
+                    ui.weak(
+                        "What you opened in Portal is shown once the current transfer is finished.",
+                    );
+                }
                 match &mut self.state {
-                    PortalAppState::Main(main) => show_main_view(main, ui, frame),
-                    PortalAppState::UriError(error) => {
+                    PortalAppState::Main(main) => {
+                        show_main_view(main, ui, frame, &mut self.transfers)
+                    }
+                    PortalAppState::UriError(error, _) => {
                         if show_uri_error(ui, error.as_ref()) {
                             update!(
                                 &mut self.state,
-                                PortalAppState::UriError(..) => PortalAppState::default());
+                                PortalAppState::UriError(_, main) => PortalAppState::Main(main));
                         }
+                        None
                     }
                 }
-            });
+            })
+            .inner
         });
+
+        // E.g. a link pasted into the receive view that asks us to send something.
+        if let Some(action) = action.inner {
+            self.queued_actions.push_back(action);
+            self.open_queued();
+            ctx.request_repaint();
+        }
+    }
+
+    // The app isn't dropped when the window is closed, as the process exits right away.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.forwarded_actions.close();
     }
 }
 
 impl PortalApp {
+    /// Opens forwarded actions in order, as long as the views they're meant for are idle.
+    fn open_queued(&mut self) {
+        while let Some(action) = self.queued_actions.pop_front() {
+            if let Err(action) = self.open_forwarded(action) {
+                self.queued_actions.push_front(action);
+                break;
+            }
+        }
+    }
+
+    /// Only the view that the action is meant for is replaced and only if it's idle, so that
+    /// e.g. opening a link while sending a file doesn't interrupt the transfer.
+    /// Returns the action if it has to wait.
+    fn open_forwarded(&mut self, action: StartupAction) -> Result<(), StartupAction> {
+        let mut main = match mem::take(&mut self.state) {
+            PortalAppState::Main(main) => main,
+            PortalAppState::UriError(error, main) => {
+                self.state = PortalAppState::UriError(error, main);
+                return match action {
+                    StartupAction::None => Ok(()),
+                    action => Err(action),
+                };
+            }
+        };
+        let result = match action {
+            StartupAction::None => Ok(()),
+            StartupAction::ShowInvalidUriError(error) => {
+                self.state = PortalAppState::UriError(error, main);
+                return Ok(());
+            }
+            StartupAction::ReceiveFile(action) if main.is_receive_idle() => {
+                main.open_receive(ReceiveView::new(action));
+                Ok(())
+            }
+            StartupAction::SendFiles(action) if main.is_send_idle() => {
+                main.open_send(SendView::new(action));
+                Ok(())
+            }
+            StartupAction::SendToPeer(action) if main.is_send_idle() => {
+                main.open_send(SendView::new_to_peer(action));
+                Ok(())
+            }
+            action => Err(action),
+        };
+        self.state = PortalAppState::Main(main);
+        result
+    }
+
     fn apply_accent(&self, ctx: &egui::Context) {
         let accent = self.accent();
         ctx.style_mut_of(Theme::Dark, visuals::apply_accent(Theme::Dark, accent));
@@ -97,7 +224,7 @@ impl PortalApp {
     fn accent(&self) -> Accent {
         match &self.state {
             PortalAppState::Main(m) => m.accent(),
-            PortalAppState::UriError(_) => Accent::Orange,
+            PortalAppState::UriError(..) => Accent::Orange,
         }
     }
 }
@@ -2,22 +2,45 @@
 
 use clap::Parser;
 use egui::{vec2, IconData, ViewportBuilder};
-use portal::{PortalApp, StartupAction};
+use portal::single_instance::{self, Instance};
+use portal::{cli, PortalApp, StartupAction};
 use std::error::Error;
+use std::io;
+use std::process::ExitCode;
 
 #[derive(Parser, Debug)]
 #[command(version)]
 struct Cli {
-    #[arg(last = true)]
-    uri: Option<String>,
+    #[command(subcommand)]
+    command: Option<cli::Command>,
+    /// A wormhole-transfer URI to receive, or files and folders to send.
+    /// Paths that are named like a subcommand have to follow `--`, e.g. `portal -- send`.
+    #[arg(value_name = "URI_OR_PATHS")]
+    args: Vec<String>,
 }
 
 #[async_std::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let args = Cli::parse();
+async fn main() -> Result<ExitCode, Box<dyn Error>> {
+    // Help, version and usage errors are printed by clap and need the console as well.
+    let args = Cli::try_parse().unwrap_or_else(|error| {
+        attach_console();
+        error.exit()
+    });
+    if args.command.is_some() {
+        attach_console();
+    }
+
+    // Log to stderr, as stdout is reserved for the output of the headless mode.
+    tracing_subscriber::fmt().with_writer(io::stderr).init();
 
-    // Log to stdout (if you run with `RUST_LOG=debug`).
-    tracing_subscriber::fmt::init();
+    if let Some(command) = args.command {
+        return Ok(cli::run(command).await);
+    }
+
+    let instance = match single_instance::acquire(&args.args) {
+        Instance::Primary(instance) => instance,
+        Instance::Forwarded => return Ok(ExitCode::SUCCESS),
+    };
 
     let mut viewport = ViewportBuilder::default().with_inner_size(vec2(320.0, 500.0));
     if let Some(icon) = icon()? {
@@ -28,15 +51,28 @@ async fn main() -> Result<(), Box<dyn Error>> {
         run_and_return: false,
         ..Default::default()
     };
-    let startup_action = StartupAction::from_uri(args.uri.as_deref());
+    let startup_action = StartupAction::from_args(&args.args);
     eframe::run_native(
         "Portal",
         options,
-        Box::new(move |cc| Ok(Box::new(PortalApp::new(cc, startup_action)))),
+        Box::new(move |cc| Ok(Box::new(PortalApp::new(cc, startup_action, instance)))),
     )?;
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Release builds on Windows don't get a console of their own as they're GUI applications,
+/// so the headless mode writes to the console of the shell that it's started from.
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // SAFETY: AttachConsole has no preconditions, it fails (which we ignore)
+    // if the process already has a console or the parent process doesn't have one.
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
 }
 
+#[cfg(not(windows))]
+fn attach_console() {}
+
 #[cfg(not(any(windows, all(debug_assertions, target_os = "macos"))))]
 fn icon() -> Result<Option<IconData>, Box<dyn Error>> {
     Ok(None)
@@ -57,3 +93,18 @@ fn icon() -> Result<Option<IconData>, Box<dyn Error>> {
         .map(Some)
         .map_err(Into::into)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_named_like_subcommands_follow_double_dash() {
+        let cli = Cli::try_parse_from(["portal", "--", "send", "receive"]).expect("valid args");
+        assert!(cli.command.is_none());
+        assert_eq!(cli.args, ["send", "receive"]);
+
+        let cli = Cli::try_parse_from(["portal", "send", "file.txt"]).expect("valid args");
+        assert!(matches!(cli.command, Some(cli::Command::Send { .. })));
+    }
+}
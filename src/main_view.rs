@@ -1,7 +1,10 @@
 use crate::font::{ICON_DOWNLOAD, ICON_UPLOAD};
+use crate::transfers::Transfers;
 use crate::visuals::Accent;
 use crate::widgets::toggle;
-use crate::{ReceiveFileAction, ReceiveView, SendView};
+use crate::{
+    ReceiveFileAction, ReceiveView, SendFilesAction, SendToPeerAction, SendView, StartupAction,
+};
 use egui::{hex_color, RichText, Ui};
 
 #[derive(Default)]
@@ -12,6 +15,24 @@ pub(crate) struct MainViewState {
 }
 
 impl MainViewState {
+    pub(crate) fn open_send(&mut self, send_view: SendView) {
+        self.send_view = send_view;
+        self.view_toggle = false;
+    }
+
+    pub(crate) fn open_receive(&mut self, receive_view: ReceiveView) {
+        self.receive_view = receive_view;
+        self.view_toggle = true;
+    }
+
+    pub(crate) fn is_send_idle(&self) -> bool {
+        self.send_view.is_idle()
+    }
+
+    pub(crate) fn is_receive_idle(&self) -> bool {
+        self.receive_view.is_idle()
+    }
+
     pub(crate) fn accent(&self) -> Accent {
         match View::from(self.view_toggle) {
             View::Send => Accent::Orange,
@@ -30,7 +51,31 @@ impl From<ReceiveFileAction> for MainViewState {
     }
 }
 
-pub(crate) fn show_main_view(state: &mut MainViewState, ui: &mut Ui, frame: &mut eframe::Frame) {
+impl From<SendFilesAction> for MainViewState {
+    fn from(value: SendFilesAction) -> Self {
+        MainViewState {
+            send_view: SendView::new(value),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<SendToPeerAction> for MainViewState {
+    fn from(value: SendToPeerAction) -> Self {
+        MainViewState {
+            send_view: SendView::new_to_peer(value),
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns what has been opened in a view that is meant for another view.
+pub(crate) fn show_main_view(
+    state: &mut MainViewState,
+    ui: &mut Ui,
+    frame: &mut eframe::Frame,
+    transfers: &mut Transfers,
+) -> Option<StartupAction> {
     let view = View::from(state.view_toggle);
 
     apply_style_overrides(view, ui.style_mut());
@@ -46,8 +91,9 @@ pub(crate) fn show_main_view(state: &mut MainViewState, ui: &mut Ui, frame: &mut
             ));
         }
 
-        state_ui(state, view, ui, frame);
-    });
+        state_ui(state, view, ui, frame, transfers)
+    })
+    .inner
 }
 
 fn apply_style_overrides(view: View, style: &mut egui::Style) {
@@ -65,7 +111,7 @@ fn show_switcher(state: &MainViewState, view: View) -> bool {
     match view {
         View::Send => matches!(
             state.send_view,
-            SendView::Ready(..) | SendView::SelectingFile(..)
+            SendView::Ready(..) | SendView::SelectingFile(_, None)
         ),
         View::Receive => state.receive_view.show_switcher(),
     }
@@ -74,14 +120,23 @@ fn show_switcher(state: &MainViewState, view: View) -> bool {
 fn ui_enabled(state: &MainViewState, view: View) -> bool {
     match view {
         View::Send => !matches!(state.send_view, SendView::SelectingFile(..)),
-        View::Receive => true,
+        View::Receive => state.receive_view.ui_enabled(),
     }
 }
 
-fn state_ui(state: &mut MainViewState, view: View, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+fn state_ui(
+    state: &mut MainViewState,
+    view: View,
+    ui: &mut egui::Ui,
+    frame: &mut eframe::Frame,
+    transfers: &mut Transfers,
+) -> Option<StartupAction> {
     match view {
-        View::Send => state.send_view.ui(ui, frame),
-        View::Receive => state.receive_view.ui(ui),
+        View::Send => {
+            state.send_view.ui(ui, frame, transfers);
+            None
+        }
+        View::Receive => state.receive_view.ui(ui, frame, transfers),
     }
 }
 
@@ -0,0 +1,41 @@
+use crate::byte_display::ByteDisplay;
+use portal_wormhole::{Progress, TransferRate};
+use std::fmt;
+use std::time::Duration;
+use ubyte::ToByteUnit;
+
+/// Formats transfer progress like "12.3 MB of 4.1 GB · 45 MB/s · 1m 20s left".
+pub(crate) struct TransferProgressDisplay<'a>(pub(crate) &'a Progress, pub(crate) &'a TransferRate);
+
+impl fmt::Display for TransferProgressDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let TransferProgressDisplay(progress, rate) = self;
+        write!(
+            f,
+            "{:.1} of {:.1}",
+            ByteDisplay(progress.value.bytes()),
+            ByteDisplay(progress.total.bytes())
+        )?;
+        if let Some(bytes_per_second) = rate.bytes_per_second {
+            write!(f, " · {:.1}/s", ByteDisplay(bytes_per_second.bytes()))?;
+        }
+        if let Some(remaining) = rate.remaining {
+            write!(f, " · {} left", DurationDisplay(remaining))?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats durations like "45s", "1m 20s" or "2h 5m".
+pub(crate) struct DurationDisplay(pub(crate) Duration);
+
+impl fmt::Display for DurationDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.0.as_secs();
+        match seconds {
+            0..60 => write!(f, "{seconds}s"),
+            60..3600 => write!(f, "{}m {}s", seconds / 60, seconds % 60),
+            _ => write!(f, "{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        }
+    }
+}
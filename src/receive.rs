@@ -1,36 +1,59 @@
+use crate::byte_display::ByteDisplay;
 use crate::egui_ext::ContextExt;
-use crate::font::{ICON_CHECK, ICON_DOWNLOAD, ICON_X};
+use crate::font::{ICON_CHECK, ICON_CLIPBOARD_COPY, ICON_DOWNLOAD, ICON_X};
+use crate::history::{Direction, Outcome, TransferDetails, TransferTracker};
+use crate::progress_display::TransferProgressDisplay;
+use crate::settings::Settings;
+use crate::transfers::Transfers;
 use crate::transit_info::TransitInfoDisplay;
 use crate::widgets::{
-    cancel_button, page, page_with_content, CancelLabel, PrimaryButton, MIN_BUTTON_SIZE,
+    self, cancel_button, code_edit, page, page_with_content, CancelLabel, PrimaryButton,
+    MIN_BUTTON_SIZE,
 };
-use crate::{update, ReceiveFileAction};
+use crate::{update, ReceiveFileAction, StartupAction};
 use eframe::egui::{Button, ProgressBar, TextEdit, Ui};
-use egui::Key;
+use egui::{Context, Key, RichText};
 use opener::{open, reveal};
 use portal_proc_macro::states;
 use portal_wormhole::receive::{
-    connect, ConnectResult, ConnectingController, ReceiveRequestController, ReceiveResult,
+    connect, ConnectResult, ConnectingController, DiskSpace, ReceiveOffer, ReceiveOptions,
+    ReceiveRequestController, ReceiveResult, ReceiveTarget, ReceiveTextController,
     ReceivingController,
 };
-use portal_wormhole::{Code, PortalError, Progress, TransitInfo};
-use std::fmt;
+use portal_wormhole::{
+    normalize_code, parse_link, Code, ContentHash, PortalError, Progress, TransitInfo,
+    WormholeTransferUri,
+};
+use rfd::{AsyncFileDialog, FileHandle};
+use std::fs;
+use std::future::Future;
 use std::path::{Path, PathBuf};
-use ubyte::{ByteUnit, ToByteUnit};
+use ubyte::ToByteUnit;
+use url::Url;
 
 #[derive(Default)]
 pub struct ReceiveView {
     state: ReceiveState,
+    /// The link that the view has been opened with.
+    link: Option<ReceiveFileAction>,
 }
 
 impl ReceiveView {
     pub fn new(action: ReceiveFileAction) -> Self {
         Self {
             state: ReceiveState::Initial(action.code.to_string()),
+            link: Some(action),
         }
     }
 }
 
+/// The rendezvous server of the link is only used as long as its code is used.
+fn rendezvous_server(link: &Option<ReceiveFileAction>, code: &str) -> Option<Url> {
+    link.as_ref()
+        .filter(|link| *link.code == normalize_code(code))
+        .and_then(|link| link.rendezvous_server.clone())
+}
+
 impl Default for ReceiveState {
     fn default() -> Self {
         ReceiveState::Initial(String::default())
@@ -43,44 +66,95 @@ states! {
     state Initial(code: String);
 
     async state Connecting(controller: ConnectingController, code: Code) -> ConnectResult {
-        new(code: Code) {
-            let (future, controller) = connect(code.clone());
+        new(code: Code, rendezvous_server: Option<Url>) {
+            let connection = Settings::load(ui.ctx()).connection_with_rendezvous_server(rendezvous_server.as_ref());
+            let (future, controller) = connect(code.clone(), connection);
             (future, controller, code)
         }
         next {
-            Ok(receive_request) => Connected(receive_request),
+            Ok(ReceiveOffer::File(receive_request)) => connected(ui, receive_request, false),
+            Ok(ReceiveOffer::Text(text_offer)) => {
+                if Settings::load(ui.ctx()).require_verification {
+                    VerifyingText(text_offer)
+                } else {
+                    ReceiveState::new_accepting_text(ui, text_offer)
+                }
+            }
             Err(PortalError::Canceled) => Default::default(),
             Err(error) => Error(error),
         }
     }
 
-    state Connected(controller: ReceiveRequestController);
+    // Tracks whether the user has confirmed the verifier, if that's required by the settings,
+    // how much of the file has been received in an interrupted transfer
+    // and whether the file fits on the disk.
+    state Connected(controller: ReceiveRequestController, verified: bool, resumable: Option<Progress>, disk_space: Option<DiskSpace>);
 
     async state Rejecting() -> Result<(), PortalError> {
-        new(request: ReceiveRequestController) { (request.reject(),) }
+        new(reject_future: impl Future<Output = Result<(), PortalError>> + Send + 'static) { (Box::pin(reject_future),) }
         next {
             Ok(()) => Default::default(),
             Err(error) => Error(error),
         }
     }
 
-    async state Receiving(controller: ReceivingController, filename: String) -> ReceiveResult {
-        new(receive_request: ReceiveRequestController) {
+    async state ChoosingDestination(controller: ReceiveRequestController) -> Option<FileHandle> {
+        new(
+            receive_request: ReceiveRequestController,
+            save_future: impl Future<Output = Option<FileHandle>> + Send + 'static,
+        ) {
+            (Box::pin(save_future), receive_request)
+        }
+        next {
+            Some(file) => {
+                let options = ReceiveOptions {
+                    target: ReceiveTarget::File(file.path().to_owned()),
+                    ..Settings::load(ui.ctx()).receive_options()
+                };
+                ReceiveState::new_receiving(ui, controller, options)
+            }
+            // The verifier has already been confirmed before choosing the destination.
+            None => connected(ui, controller, true),
+        }
+    }
+
+    async state Receiving(controller: ReceivingController, filename: String, tracker: TransferTracker) -> ReceiveResult {
+        new(receive_request: ReceiveRequestController, options: ReceiveOptions) {
             let filename = receive_request.file_name();
+            let tracker = TransferTracker::new(Direction::Received, filename.clone());
             let ctx = ui.ctx().clone();
-            let (future, controller) = receive_request.accept(move || ctx.request_repaint());
-            (Box::pin(future), controller, filename)
+            let (future, controller) = receive_request.accept(options, move || ctx.request_repaint());
+            (Box::pin(future), controller, filename, tracker)
         }
         next {
-            Ok(path) => Completed(path),
-            Err(PortalError::Canceled) => Default::default(),
+            result => {
+                let mut controller = controller;
+                record_transfer(ui.ctx(), tracker, &mut controller, &result);
+                match result {
+                    Ok(path) => Completed(path, controller.content_hash().cloned()),
+                    Err(PortalError::Canceled) => Default::default(),
+                    Err(error) => Error(error),
+                }
+            }
+        }
+    }
+
+    // The message is only acknowledged and shown once the user has confirmed the verifier.
+    state VerifyingText(controller: ReceiveTextController);
+
+    async state AcceptingText() -> Result<String, PortalError> {
+        new(text_offer: ReceiveTextController) { (Box::pin(text_offer.accept()),) }
+        next {
+            Ok(text) => ReceivedText(text),
             Err(error) => Error(error),
         }
     }
 
     state Error(error: PortalError);
 
-    state Completed(path: PathBuf);
+    state Completed(path: PathBuf, content_hash: Option<ContentHash>);
+
+    state ReceivedText(text: String);
 }
 
 impl ReceiveView {
@@ -88,15 +162,52 @@ impl ReceiveView {
         matches!(self.state, ReceiveState::Initial(_))
     }
 
-    pub fn ui(&mut self, ui: &mut Ui) {
+    pub fn ui_enabled(&self) -> bool {
+        !matches!(self.state, ReceiveState::ChoosingDestination(..))
+    }
+
+    /// Whether the view can be replaced without interrupting a transfer or an offer.
+    pub fn is_idle(&self) -> bool {
+        matches!(
+            self.state,
+            ReceiveState::Initial(_)
+                | ReceiveState::Error(_)
+                | ReceiveState::Completed(..)
+                | ReceiveState::ReceivedText(_)
+        )
+    }
+
+    /// Returns what the user has opened in this view that is meant for another view,
+    /// i.e. the link of a peer that waits for us to send something.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        frame: &mut eframe::Frame,
+        transfers: &mut Transfers,
+    ) -> Option<StartupAction> {
         self.state.next(ui);
 
+        let mut action = None;
         match &mut self.state {
             ReceiveState::Initial(ref mut code) => {
+                // Links are pasted into the code field as is, so we extract the code (and rendezvous server).
+                let link = dropped_link(ui).or_else(|| parse_link(code));
+                match link.map(StartupAction::from_wormhole_transfer_uri) {
+                    Some(StartupAction::ReceiveFile(link)) => {
+                        *code = link.code.to_string();
+                        self.link = Some(link);
+                    }
+                    Some(link) => {
+                        code.clear();
+                        action = Some(link);
+                    }
+                    None => {}
+                }
                 if let Some(ReceivePageResponse::Connect) = show_receive_file_page(ui, code) {
+                    let rendezvous_server = rendezvous_server(&self.link, code);
                     update! {
                         &mut self.state,
-                        ReceiveState::Initial(code) => ReceiveState::new_connecting(ui, Code(code))
+                        ReceiveState::Initial(code) => ReceiveState::new_connecting(ui, Code(normalize_code(&code)), rendezvous_server)
                     }
                 }
             }
@@ -108,19 +219,75 @@ impl ReceiveView {
                 self.back_button(ui);
                 page(ui, "File Transfer Failed", error, ICON_X);
             }
-            ReceiveState::Connected(ref receive_request) => {
-                if let Some(response) = show_connected_page(ui, receive_request) {
+            ReceiveState::ChoosingDestination(_, ref receive_request) => {
+                _ = show_connected_page(ui, receive_request, &mut true, None, None);
+            }
+            ReceiveState::Connected(
+                ref receive_request,
+                ref mut verified,
+                resumable,
+                disk_space,
+            ) => {
+                if let Some(response) =
+                    show_connected_page(ui, receive_request, verified, *resumable, *disk_space)
+                {
                     update! {
                         &mut self.state,
-                        ReceiveState::Connected(receive_request) => match response {
-                            ConnectedPageResponse::Accept => ReceiveState::new_receiving(ui, receive_request),
-                            ConnectedPageResponse::Reject => ReceiveState::new_rejecting(ui, receive_request),
+                        ReceiveState::Connected(receive_request, _, _, _) => match response {
+                            ConnectedPageResponse::Accept => {
+                                let options = Settings::load(ui.ctx()).receive_options();
+                                ReceiveState::new_receiving(ui, receive_request, options)
+                            }
+                            ConnectedPageResponse::Resume => {
+                                let options = ReceiveOptions {
+                                    resume: true,
+                                    ..Settings::load(ui.ctx()).receive_options()
+                                };
+                                ReceiveState::new_receiving(ui, receive_request, options)
+                            }
+                            ConnectedPageResponse::SaveAs => {
+                                let save_future = AsyncFileDialog::new()
+                                    .set_parent(frame)
+                                    .set_file_name(receive_request.sanitized_file_name())
+                                    .save_file();
+                                ReceiveState::new_choosing_destination(ui, receive_request, save_future)
+                            }
+                            ConnectedPageResponse::Reject => {
+                                record_rejected_offer(ui.ctx(), &receive_request);
+                                ReceiveState::new_rejecting(ui, receive_request.reject())
+                            }
                         }
                     }
                 }
             }
-            ReceiveState::Receiving(_, ref mut controller, ref filename) => {
-                show_receiving_page(ui, controller, filename);
+            ReceiveState::Receiving(_, ref mut controller, ref filename, _) => {
+                if let Some(ReceivingPageResponse::ContinueInBackground) =
+                    show_receiving_page(ui, controller, filename)
+                {
+                    update! {
+                        &mut self.state,
+                        ReceiveState::Receiving(promise, controller, filename, tracker) => {
+                            transfers.push_receive(promise, controller, filename, tracker);
+                            ReceiveState::default()
+                        }
+                    }
+                }
+            }
+            ReceiveState::VerifyingText(text_offer) => {
+                if let Some(response) = show_verifying_text_page(ui, text_offer) {
+                    update! {
+                        &mut self.state,
+                        ReceiveState::VerifyingText(text_offer) => match response {
+                            VerifyingTextPageResponse::Accept => ReceiveState::new_accepting_text(ui, text_offer),
+                            VerifyingTextPageResponse::Reject => ReceiveState::new_rejecting(ui, text_offer.reject()),
+                        }
+                    }
+                }
+            }
+            ReceiveState::AcceptingText(_) => {
+                page_with_content(ui, "Receive Text", "Receiving Text", ICON_DOWNLOAD, |ui| {
+                    ui.spinner();
+                });
             }
             ReceiveState::Rejecting(_) => {
                 page_with_content(
@@ -133,13 +300,20 @@ impl ReceiveView {
                     },
                 );
             }
-            ReceiveState::Completed(downloaded_path) => {
-                if let Some(CompletedPageResponse::Back) = show_completed_page(ui, downloaded_path)
+            ReceiveState::Completed(downloaded_path, content_hash) => {
+                if let Some(CompletedPageResponse::Back) =
+                    show_completed_page(ui, downloaded_path, content_hash.as_ref())
                 {
                     self.state = ReceiveState::default();
                 }
             }
+            ReceiveState::ReceivedText(text) => {
+                if let Some(CompletedPageResponse::Back) = show_received_text_page(ui, text) {
+                    self.state = ReceiveState::default();
+                }
+            }
         }
+        action
     }
 
     fn back_button(&mut self, ui: &mut Ui) {
@@ -149,6 +323,52 @@ impl ReceiveView {
     }
 }
 
+/// Looks up what's already on the disk before showing the offer.
+fn connected(ui: &Ui, receive_request: ReceiveRequestController, verified: bool) -> ReceiveState {
+    let options = Settings::load(ui.ctx()).receive_options();
+    let resumable = receive_request.resumable(&options);
+    let disk_space = receive_request.disk_space(&options);
+    ReceiveState::Connected(receive_request, verified, resumable, disk_space)
+}
+
+/// Dropped links arrive either as the content or the name of the dropped item,
+/// or as a link file (`.url` on Windows, `.webloc` on macOS) that is saved when a link is dragged out of a browser.
+fn dropped_link(ui: &Ui) -> Option<WormholeTransferUri> {
+    ui.input(|input| {
+        input.raw.dropped_files.iter().find_map(|file| {
+            file.bytes
+                .as_deref()
+                .and_then(|bytes| std::str::from_utf8(bytes).ok())
+                .and_then(link_in_text)
+                .or_else(|| parse_link(&file.name))
+                .or_else(|| {
+                    file.path
+                        .as_deref()
+                        .filter(|path| is_link_file(path))
+                        .and_then(|path| fs::read_to_string(path).ok())
+                        .and_then(|text| link_in_text(&text))
+                })
+        })
+    })
+}
+
+fn is_link_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("url") || extension.eq_ignore_ascii_case("webloc")
+    })
+}
+
+/// Finds the link in plain text or in the contents of a link file.
+fn link_in_text(text: &str) -> Option<WormholeTransferUri> {
+    parse_link(text.trim()).or_else(|| {
+        text.lines().map(str::trim).find_map(|line| {
+            line.strip_prefix("URL=")
+                .or_else(|| line.strip_prefix("<string>")?.strip_suffix("</string>"))
+                .and_then(parse_link)
+        })
+    })
+}
+
 #[must_use]
 enum ReceivePageResponse {
     Connect,
@@ -161,11 +381,7 @@ fn show_receive_file_page(ui: &mut Ui, code: &mut String) -> Option<ReceivePageR
         "Enter the transmit code from the sender",
         ICON_DOWNLOAD,
         |ui| {
-            if ui
-                .add(TextEdit::singleline(code).hint_text("Code"))
-                .lost_focus()
-                && ui.input(|input| input.key_pressed(Key::Enter))
-            {
+            if code_edit(ui, code).lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) {
                 return Some(ReceivePageResponse::Connect);
             }
             ui.add_space(5.0);
@@ -190,6 +406,8 @@ fn show_receive_file_page(ui: &mut Ui, code: &mut String) -> Option<ReceivePageR
 #[must_use]
 enum ConnectedPageResponse {
     Accept,
+    Resume,
+    SaveAs,
     Reject,
 }
 
@@ -212,39 +430,206 @@ fn show_connecting_page(ui: &mut Ui, controller: &mut ConnectingController, code
 fn show_connected_page(
     ui: &mut Ui,
     receive_request: &ReceiveRequestController,
+    verified: &mut bool,
+    resumable: Option<Progress>,
+    disk_space: Option<DiskSpace>,
 ) -> Option<ConnectedPageResponse> {
     if cancel_button(ui, CancelLabel::Cancel) {
         return Some(ConnectedPageResponse::Reject);
     }
 
+    let kind = if receive_request.is_directory() {
+        "folder"
+    } else {
+        "file"
+    };
     let text = format!(
-        "Your peer wants to send you \"{}\" (Size: {}).\nDo you want to download this file?",
+        "Your peer wants to send you \"{}\" (Size: {}).\nDo you want to download this {kind}?",
         receive_request.file_name(),
         ByteDisplay(receive_request.filesize().bytes())
     );
 
+    let require_verification = Settings::load(ui.ctx()).require_verification;
+
     page_with_content(ui, "Receive File", text, ICON_DOWNLOAD, |ui| {
-        if ui
-            .add(PrimaryButton::new("Accept").min_size(MIN_BUTTON_SIZE))
-            .clicked()
-        {
-            return Some(ConnectedPageResponse::Accept);
+        widgets::verifier(ui, receive_request.verifier());
+        if require_verification {
+            ui.checkbox(verified, "The sender sees the same symbols");
         }
+        if let Some(disk_space) = disk_space {
+            show_disk_space(ui, disk_space);
+        }
+        ui.add_space(10.0);
+
+        // "Save As…" stays enabled, as the file may fit on a different disk.
+        let fits = disk_space.is_none_or(|disk_space| disk_space.is_sufficient());
+        ui.add_enabled_ui(*verified || !require_verification, |ui| {
+            if let Some(Progress { value, total }) = resumable {
+                if ui
+                    .add_enabled(fits, PrimaryButton::new("Resume").min_size(MIN_BUTTON_SIZE))
+                    .on_hover_text(format!(
+                        "{} of {} have been received before the transfer was interrupted",
+                        ByteDisplay(value.bytes()),
+                        ByteDisplay(total.bytes())
+                    ))
+                    .clicked()
+                {
+                    return Some(ConnectedPageResponse::Resume);
+                }
+
+                ui.add_space(5.0);
+
+                if ui
+                    .add_enabled(fits, Button::new("Start Over").min_size(MIN_BUTTON_SIZE))
+                    .clicked()
+                {
+                    return Some(ConnectedPageResponse::Accept);
+                }
+            } else if ui
+                .add_enabled(fits, PrimaryButton::new("Accept").min_size(MIN_BUTTON_SIZE))
+                .clicked()
+            {
+                return Some(ConnectedPageResponse::Accept);
+            }
 
-        None
+            ui.add_space(5.0);
+
+            if ui
+                .add(Button::new("Save As…").min_size(MIN_BUTTON_SIZE))
+                .clicked()
+            {
+                return Some(ConnectedPageResponse::SaveAs);
+            }
+
+            None
+        })
+        .inner
     })
 }
 
-fn show_receiving_page(ui: &mut Ui, controller: &mut ReceivingController, filename: &str) {
-    let Progress {
-        value: received,
-        total,
-    } = *controller.progress();
+#[must_use]
+enum VerifyingTextPageResponse {
+    Accept,
+    Reject,
+}
 
+fn show_verifying_text_page(
+    ui: &mut Ui,
+    text_offer: &ReceiveTextController,
+) -> Option<VerifyingTextPageResponse> {
+    if cancel_button(ui, CancelLabel::Cancel) {
+        return Some(VerifyingTextPageResponse::Reject);
+    }
+
+    page_with_content(
+        ui,
+        "Verify Your Peer",
+        "Your peer wants to send you a text message.\nBefore reading it, make sure that the sender sees the same symbols.",
+        ICON_DOWNLOAD,
+        |ui| {
+            widgets::verifier(ui, text_offer.verifier());
+            ui.add_space(10.0);
+            ui.add(PrimaryButton::new("Symbols Match").min_size(MIN_BUTTON_SIZE))
+                .clicked()
+                .then_some(VerifyingTextPageResponse::Accept)
+        },
+    )
+}
+
+fn show_disk_space(ui: &mut Ui, disk_space: DiskSpace) {
+    let available = ByteDisplay(disk_space.available.bytes());
+    if disk_space.is_sufficient() {
+        ui.label(RichText::new(format!("{available} available on the disk")).weak());
+    } else {
+        ui.colored_label(
+            ui.visuals().error_fg_color,
+            format!(
+                "Not enough free disk space ({available} available, {} needed).\nUse \"Save As…\" to save it to a different disk.",
+                ByteDisplay(disk_space.required.bytes())
+            ),
+        );
+    }
+}
+
+pub(crate) fn record_transfer(
+    ctx: &Context,
+    tracker: TransferTracker,
+    controller: &mut ReceivingController,
+    result: &ReceiveResult,
+) {
+    let size = Some(controller.progress().total).filter(|total| *total > 0);
+    let duration = controller.rate().elapsed;
+    let content_hash = controller.content_hash().cloned();
+    tracker.finish(
+        ctx,
+        result
+            .as_ref()
+            .err()
+            .map_or(Outcome::Completed, Outcome::from),
+        TransferDetails {
+            transit_info: controller.transit_info(),
+            size,
+            duration,
+            path: result.as_ref().ok().cloned(),
+            content_hash,
+        },
+    );
+}
+
+fn record_rejected_offer(ctx: &Context, receive_request: &ReceiveRequestController) {
+    TransferTracker::new(Direction::Received, receive_request.file_name()).finish(
+        ctx,
+        Outcome::Rejected,
+        TransferDetails {
+            size: Some(receive_request.filesize()),
+            ..Default::default()
+        },
+    );
+}
+
+#[must_use]
+enum ReceivingPageResponse {
+    ContinueInBackground,
+}
+
+fn show_receiving_page(
+    ui: &mut Ui,
+    controller: &mut ReceivingController,
+    filename: &str,
+) -> Option<ReceivingPageResponse> {
     if cancel_button(ui, CancelLabel::Cancel) {
         controller.cancel();
     }
 
+    if let Some(&Progress {
+        value: extracted,
+        total,
+    }) = controller.extraction_progress()
+    {
+        page_with_content(
+            ui,
+            "Extracting Archive",
+            format!("Extracting \"{filename}\""),
+            ICON_DOWNLOAD,
+            |ui| {
+                ui.add(ProgressBar::new(fraction(extracted, total)).animate(true));
+            },
+        );
+    } else {
+        show_receiving_progress(ui, controller, filename);
+    }
+
+    ui.add_space(10.0);
+    ui.button("Continue in Background")
+        .on_hover_text("Frees this view for another transfer")
+        .clicked()
+        .then_some(ReceivingPageResponse::ContinueInBackground)
+}
+
+fn show_receiving_progress(ui: &mut Ui, controller: &mut ReceivingController, filename: &str) {
+    let progress = *controller.progress();
+    let rate = *controller.rate();
+
     match controller.transit_info() {
         Some(transit_info) => page_with_content(
             ui,
@@ -252,7 +637,11 @@ fn show_receiving_page(ui: &mut Ui, controller: &mut ReceivingController, filena
             transit_info_message(transit_info, filename),
             ICON_DOWNLOAD,
             |ui| {
-                ui.add(ProgressBar::new((received as f64 / total as f64) as f32).animate(true));
+                ui.add(ProgressBar::new(fraction(progress.value, progress.total)).animate(true));
+                ui.add_space(5.0);
+                ui.label(
+                    RichText::new(TransferProgressDisplay(&progress, &rate).to_string()).weak(),
+                );
             },
         ),
         None => page_with_content(
@@ -267,28 +656,50 @@ fn show_receiving_page(ui: &mut Ui, controller: &mut ReceivingController, filena
     }
 }
 
+pub(crate) fn fraction(value: u64, total: u64) -> f32 {
+    if total == 0 {
+        1.0
+    } else {
+        (value as f64 / total as f64) as f32
+    }
+}
+
 fn transit_info_message(transit_info: &TransitInfo, filename: &str) -> String {
     format!("File \"{filename}\"{}", TransitInfoDisplay(transit_info))
 }
 
-fn show_completed_page(ui: &mut Ui, downloaded_path: &Path) -> Option<CompletedPageResponse> {
+fn show_completed_page(
+    ui: &mut Ui,
+    downloaded_path: &Path,
+    content_hash: Option<&ContentHash>,
+) -> Option<CompletedPageResponse> {
     if cancel_button(ui, CancelLabel::Back) {
         return Some(CompletedPageResponse::Back);
     }
 
     let filename = downloaded_path.file_name().expect("path with a file name");
+    let kind = if downloaded_path.is_dir() {
+        "Folder"
+    } else {
+        "File"
+    };
+    let folder = downloaded_path
+        .parent()
+        .and_then(Path::file_name)
+        .unwrap_or_default();
 
     page_with_content(
         ui,
         "File Transfer Successful",
         format!(
-            "File \"{}\" has been saved to your Downloads folder",
-            filename.to_string_lossy()
+            "{kind} \"{}\" has been saved to the \"{}\" folder",
+            filename.to_string_lossy(),
+            folder.to_string_lossy()
         ),
         ICON_CHECK,
         |ui| {
             if ui
-                .add(PrimaryButton::new("Open File").min_size(MIN_BUTTON_SIZE))
+                .add(PrimaryButton::new(format!("Open {kind}")).min_size(MIN_BUTTON_SIZE))
                 .clicked()
             {
                 _ = open(downloaded_path);
@@ -302,6 +713,39 @@ fn show_completed_page(ui: &mut Ui, downloaded_path: &Path) -> Option<CompletedP
             {
                 _ = reveal(downloaded_path);
             }
+
+            if let Some(content_hash) = content_hash {
+                ui.add_space(5.0);
+                widgets::content_hash(ui, content_hash);
+            }
+        },
+    );
+
+    None
+}
+
+fn show_received_text_page(ui: &mut Ui, text: &str) -> Option<CompletedPageResponse> {
+    if cancel_button(ui, CancelLabel::Back) {
+        return Some(CompletedPageResponse::Back);
+    }
+
+    page_with_content(
+        ui,
+        "Text Received",
+        "Your peer sent you a text message",
+        ICON_CHECK,
+        |ui| {
+            ui.add(TextEdit::multiline(&mut &*text).desired_rows(3));
+            ui.add_space(5.);
+            if ui
+                .add(
+                    PrimaryButton::new(format!("{ICON_CLIPBOARD_COPY} Copy Text"))
+                        .min_size(MIN_BUTTON_SIZE),
+                )
+                .clicked()
+            {
+                ui.output_mut(|output| output.copied_text = text.to_owned());
+            }
         },
     );
 
@@ -313,29 +757,25 @@ enum CompletedPageResponse {
     Back,
 }
 
-struct ByteDisplay(ByteUnit);
-
-// Same as https://github.com/SergioBenitez/ubyte/blob/master/src/byte_unit.rs#L442
-// except with a space between value and suffix.
-impl fmt::Display for ByteDisplay {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        const NO_BREAK_SPACE: &str = "\u{00A0}";
-        let (whole, rem, suffix, unit) = self.0.repr();
-        let width = f.width().unwrap_or(0);
-        if rem != 0f64 && f.precision().map(|p| p > 0).unwrap_or(true) {
-            let p = f.precision().unwrap_or(2);
-            let k = 10u64.saturating_pow(p as u32) as f64;
-            write!(
-                f,
-                "{:0width$}.{:0p$.0}{NO_BREAK_SPACE}{}",
-                whole,
-                rem * k,
-                suffix,
-            )
-        } else if rem > 0.5f64 {
-            ((whole.bytes() + 1) * unit).fmt(f)
-        } else {
-            write!(f, "{whole:0width$}{NO_BREAK_SPACE}{suffix}")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_link_in_link_files() {
+        let windows =
+            "[InternetShortcut]\r\nURL=https://wormhole-transfer.link/#7-guitarist-revenge\r\n";
+        let macos = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>URL</key>
+	<string>wormhole-transfer:7-guitarist-revenge</string>
+</dict>
+</plist>"#;
+        for text in [windows, macos, " wormhole-transfer:7-guitarist-revenge\n"] {
+            let uri = link_in_text(text).expect("link to be found");
+            assert_eq!(uri.code.to_string(), "7-guitarist-revenge");
         }
+        assert!(link_in_text("7-guitarist-revenge").is_none());
     }
 }
@@ -1,83 +1,161 @@
+use crate::byte_display::ByteDisplay;
 use crate::egui_ext::ContextExt;
 use crate::font::{ICON_CHECK, ICON_CLIPBOARD_COPY, ICON_LINK, ICON_TICKET, ICON_UPLOAD, ICON_X};
+use crate::history::{Direction, Outcome, TransferDetails, TransferTracker};
+use crate::progress_display::TransferProgressDisplay;
+use crate::settings::Settings;
+use crate::transfers::Transfers;
 use crate::transit_info::TransitInfoDisplay;
-use crate::update;
 use crate::widgets::{
-    cancel_button, page, page_with_content, CancelLabel, PrimaryButton, MIN_BUTTON_SIZE,
+    self, cancel_button, page_with_content, CancelLabel, PrimaryButton, MIN_BUTTON_SIZE,
 };
-use eframe::egui::{Button, Key, Modifiers, ProgressBar, Ui};
-use egui::{InputState, RichText};
+use crate::{update, SendFilesAction, SendToPeerAction};
+use eframe::egui::{Button, Key, Modifiers, ProgressBar, TextEdit, Ui};
+use egui::{CollapsingHeader, Context, Id, InputState, RichText, Slider};
 use portal_proc_macro::states;
-use portal_wormhole::send::{send, SendRequest, SendingController, SendingProgress};
-use portal_wormhole::{Code, PortalError, Progress, SharableWormholeTransferUri};
+use portal_wormhole::send::{
+    send, PackingProgress, SendCode, SendOptions, SendRequest, SendingController, SendingProgress,
+    DEFAULT_WORD_COUNT,
+};
+use portal_wormhole::{
+    normalize_code, share_link, Code, ContentHash, PortalError, Progress, TransferRate, Verifier,
+};
 use rfd::{AsyncFileDialog, FileHandle};
 use std::fmt;
 use std::future::Future;
+use std::mem;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
+use ubyte::ToByteUnit;
+use url::Url;
 
 states! {
     pub enum SendView;
 
-    state Ready();
+    state Ready(text: String);
+
+    // The peer has created the code and waits for us to pick what to send.
+    state Invited(peer: SendToPeerAction, text: String);
+
+    // Sending is started on the first frame, as it needs the UI.
+    state Starting(request: SendRequest);
 
-    async state SelectingFile() -> Option<Vec<FileHandle>> {
-        new(pick_future: impl Future<Output = Option<Vec<FileHandle>>> + Send + 'static) {
-            (Box::pin(pick_future),)
+    async state SelectingFile(peer: Option<SendToPeerAction>) -> Option<Vec<FileHandle>> {
+        new(pick_future: impl Future<Output = Option<Vec<FileHandle>>> + Send + 'static, peer: Option<SendToPeerAction>) {
+            (Box::pin(pick_future), peer)
         }
         next {
-            None => Ready(),
+            None => SendView::from_peer(peer),
             Some(paths) => {
                 if let Some(request) = SendRequest::from_paths(paths.into_iter().map(|p| p.path().to_owned()).collect()) {
-                    SendView::new_sending(ui, request)
+                    SendView::new_sending(ui, request, Recipient::new(ui.ctx(), peer))
                 } else {
-                    Ready()
+                    SendView::from_peer(peer)
                 }
             }
         }
     }
 
-    async state Sending(controller: SendingController, request: SendRequest) -> Result<(), (PortalError, SendRequest)> {
-        new(request: SendRequest) {
+    async state Sending(controller: SendingController, request: SendRequest, recipient: Recipient, tracker: Option<TransferTracker>) -> Result<(), (PortalError, SendRequest)> {
+        new(request: SendRequest, recipient: Recipient) {
             let ctx = ui.ctx().clone();
-            let (future, controller) = send(request.clone(), move || ctx.request_repaint());
-            (Box::pin(future), controller, request)
+            let settings = Settings::load(&ctx);
+            let tracker = start_tracking(&request);
+            let connection = settings.connection_with_rendezvous_server(recipient.rendezvous_server.as_ref());
+            let options = SendOptions { code: recipient.code.clone(), ..settings.send_options() };
+            let (future, controller) = send(request.clone(), connection, options, move || ctx.request_repaint());
+            (Box::pin(future), controller, request, recipient, tracker)
         }
         next {
-            Ok(_) => Complete(request),
-            Err((PortalError::Canceled, _)) => SendView::default(),
-            Err((error, send_request)) => Error(error, send_request),
+            result => {
+                let mut controller = controller;
+                if let Some(tracker) = tracker {
+                    record_transfer(ui.ctx(), tracker, &mut controller, &result);
+                }
+                match result {
+                    Ok(_) => Complete(request, controller.content_hash().cloned()),
+                    Err((PortalError::Canceled, _)) => SendView::default(),
+                    Err((error, send_request)) => Error(error, send_request, recipient),
+                }
+            }
         }
     }
 
-    state Error(error: PortalError, send_request: SendRequest);
+    // The recipient is kept so that retrying uses the same code.
+    state Error(error: PortalError, send_request: SendRequest, recipient: Recipient);
 
-    state Complete(request: SendRequest);
+    state Complete(request: SendRequest, content_hash: Option<ContentHash>);
 }
 
 impl Default for SendView {
     fn default() -> Self {
-        SendView::Ready()
+        SendView::Ready(String::new())
     }
 }
 
 impl SendView {
-    pub fn ui(&mut self, ui: &mut Ui, frame: &mut eframe::Frame) {
+    pub fn new(action: SendFilesAction) -> Self {
+        SendRequest::from_paths(action.paths)
+            .map(SendView::Starting)
+            .unwrap_or_default()
+    }
+
+    pub fn new_to_peer(action: SendToPeerAction) -> Self {
+        SendView::Invited(action, String::new())
+    }
+
+    /// Whether the view can be replaced without interrupting a transfer or an invitation.
+    pub fn is_idle(&self) -> bool {
+        matches!(
+            self,
+            SendView::Ready(_) | SendView::Error(..) | SendView::Complete(..)
+        )
+    }
+
+    fn from_peer(peer: Option<SendToPeerAction>) -> Self {
+        peer.map(SendView::new_to_peer).unwrap_or_default()
+    }
+
+    fn peer(&self) -> Option<SendToPeerAction> {
+        match self {
+            SendView::Invited(peer, _) => Some(peer.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, frame: &mut eframe::Frame, transfers: &mut Transfers) {
+        update!(self, SendView::Starting(request) => SendView::new_sending(ui, request, Recipient::new(ui.ctx(), None)));
         self.next(ui);
 
-        if let SendView::Ready() | SendView::Complete(..) = self {
+        if let SendView::Ready(_) | SendView::Invited(..) | SendView::Complete(..) = self {
             self.accept_dropped_file(ui);
         }
 
         match self {
-            SendView::Ready() | SendView::SelectingFile(..) => {
+            SendView::Ready(_) | SendView::SelectingFile(_, None) | SendView::Starting(_) => {
                 self.show_file_selection_page(ui, frame)
             }
-            SendView::Sending(_, ref mut controller, ref send_request) => {
-                show_transfer_progress(ui, controller, send_request)
+            SendView::Invited(ref peer, _) | SendView::SelectingFile(_, Some(ref peer)) => {
+                let code = peer.code.clone();
+                self.show_invitation_page(ui, frame, &code)
+            }
+            SendView::Sending(_, ref mut controller, ref send_request, ref recipient, _) => {
+                if let Some(TransferProgressResponse::ContinueInBackground) =
+                    show_transfer_progress(ui, controller, send_request, recipient)
+                {
+                    update!(
+                        self,
+                        SendView::Sending(promise, controller, request, _, tracker) => {
+                            transfers.push_send(promise, controller, request, tracker);
+                            SendView::default()
+                        }
+                    );
+                }
             }
-            SendView::Error(ref error, _) => self.show_error_page(ui, error.to_string()),
-            SendView::Complete(ref send_request) => {
-                self.show_transfer_completed_page(ui, send_request.clone())
+            SendView::Error(ref error, _, _) => self.show_error_page(ui, error.to_string()),
+            SendView::Complete(ref send_request, ref content_hash) => {
+                let content_hash = content_hash.clone();
+                self.show_transfer_completed_page(ui, send_request.clone(), content_hash.as_ref())
             }
         }
     }
@@ -86,20 +164,46 @@ impl SendView {
         page_with_content(
             ui,
             "Send File",
-            "Select or drop the file or directory to send.",
+            "Select or drop the file or directory to send, or enter a text message.",
+            ICON_UPLOAD,
+            |ui| self.show_file_selection(ui, frame),
+        );
+    }
+
+    fn show_invitation_page(&mut self, ui: &mut Ui, frame: &mut eframe::Frame, code: &Code) {
+        self.back_button(ui);
+
+        page_with_content(
+            ui,
+            "Send to Peer",
+            format!("Your peer is waiting for you to send something using the code \"{code}\".\nSelect or drop the file or directory to send, or enter a text message."),
             ICON_UPLOAD,
             |ui| self.show_file_selection(ui, frame),
         );
     }
 
     fn show_file_selection(&mut self, ui: &mut Ui, frame: &mut eframe::Frame) {
+        let code_valid = self.peer().is_some() || AdvancedOptions::load(ui.ctx()).code().is_ok();
+        ui.add_enabled_ui(code_valid, |ui| {
+            self.show_file_and_text_selection(ui, frame)
+        });
+
+        if let SendView::Ready(_) = self {
+            ui.add_space(10.);
+            show_advanced_options(ui);
+        }
+    }
+
+    fn show_file_and_text_selection(&mut self, ui: &mut Ui, frame: &mut eframe::Frame) {
         let select_file_button = PrimaryButton::new("Select File").min_size(MIN_BUTTON_SIZE);
         if ui.add(select_file_button).clicked()
-            || ui.input_mut(|input| input.consume_key(Modifiers::COMMAND, Key::O))
+            || (ui.is_enabled()
+                && ui.input_mut(|input| input.consume_key(Modifiers::COMMAND, Key::O)))
         {
             *self = SendView::new_selecting_file(
                 ui,
                 AsyncFileDialog::new().set_parent(frame).pick_files(),
+                self.peer(),
             );
         }
 
@@ -110,8 +214,18 @@ impl SendView {
             *self = SendView::new_selecting_file(
                 ui,
                 AsyncFileDialog::new().set_parent(frame).pick_folders(),
+                self.peer(),
             );
         }
+
+        let peer = self.peer();
+        if let SendView::Ready(text) | SendView::Invited(_, text) = self {
+            ui.add_space(15.);
+            if let Some(TextInputResponse::Send) = show_text_input(ui, text) {
+                let request = SendRequest::Text(mem::take(text));
+                *self = SendView::new_sending(ui, request, Recipient::new(ui.ctx(), peer));
+            }
+        }
     }
 
     fn show_error_page(&mut self, ui: &mut Ui, error: String) {
@@ -121,29 +235,41 @@ impl SendView {
             if ui.button("Retry").clicked() {
                 update!(
                     self,
-                    SendView::Error(_, send_request) => SendView::new_sending(ui, send_request)
+                    SendView::Error(_, send_request, recipient) => SendView::new_sending(ui, send_request, recipient)
                 );
             }
         });
     }
 
-    fn show_transfer_completed_page(&mut self, ui: &mut Ui, send_request: SendRequest) {
+    fn show_transfer_completed_page(
+        &mut self,
+        ui: &mut Ui,
+        send_request: SendRequest,
+        content_hash: Option<&ContentHash>,
+    ) {
         let request_title = SendRequestDisplay(&send_request).to_string();
         self.back_button(ui);
-        page(
+        page_with_content(
             ui,
             "File Transfer Successful",
             format!("Successfully sent {request_title}"),
             ICON_CHECK,
+            |ui| {
+                if let Some(content_hash) = content_hash {
+                    widgets::content_hash(ui, content_hash);
+                }
+            },
         );
     }
 
     fn accept_dropped_file(&mut self, ui: &mut Ui) {
-        if ui.is_enabled() {
+        let code_valid = self.peer().is_some() || AdvancedOptions::load(ui.ctx()).code().is_ok();
+        if ui.is_enabled() && code_valid {
             let dropped_file_paths: Vec<_> = ui.ctx().input(dropped_file_paths);
 
             if let Some(send_request) = SendRequest::from_paths(dropped_file_paths) {
-                *self = SendView::new_sending(ui, send_request)
+                *self =
+                    SendView::new_sending(ui, send_request, Recipient::new(ui.ctx(), self.peer()))
             }
         }
     }
@@ -155,6 +281,128 @@ impl SendView {
     }
 }
 
+/// Who a transfer is sent to.
+#[derive(Clone)]
+pub(crate) struct Recipient {
+    code: SendCode,
+    /// The rendezvous server of the peer's link, if any.
+    rendezvous_server: Option<Url>,
+}
+
+impl Recipient {
+    /// Transfers that don't go to a waiting peer use the code from the advanced options.
+    fn new(ctx: &Context, peer: Option<SendToPeerAction>) -> Self {
+        match peer {
+            Some(peer) => Recipient {
+                code: SendCode::Peer(peer.code),
+                rendezvous_server: peer.rendezvous_server,
+            },
+            None => Recipient {
+                code: AdvancedOptions::take_code(ctx),
+                rendezvous_server: None,
+            },
+        }
+    }
+}
+
+/// Options from the "Advanced" section, which are kept for the session
+/// and apply to the next transfer that doesn't send to a waiting peer.
+#[derive(Clone)]
+struct AdvancedOptions {
+    word_count: usize,
+    custom_code: String,
+}
+
+impl Default for AdvancedOptions {
+    fn default() -> Self {
+        Self {
+            word_count: DEFAULT_WORD_COUNT,
+            custom_code: String::new(),
+        }
+    }
+}
+
+impl AdvancedOptions {
+    const WORD_COUNTS: RangeInclusive<usize> = 2..=8;
+
+    fn load(ctx: &Context) -> Self {
+        ctx.data(|d| d.get_temp(Self::id())).unwrap_or_default()
+    }
+
+    fn store(self, ctx: &Context) {
+        ctx.data_mut(|d| d.insert_temp(Self::id(), self));
+    }
+
+    fn id() -> Id {
+        Id::new("advanced_send_options")
+    }
+
+    fn code(&self) -> Result<SendCode, String> {
+        match normalize_code(&self.custom_code).as_str() {
+            "" => Ok(SendCode::Generated {
+                word_count: self.word_count,
+            }),
+            code => code
+                .parse()
+                .map(SendCode::Custom)
+                .map_err(|error| format!("Invalid code: {error}")),
+        }
+    }
+
+    /// A code can only be used once, so the custom code is cleared once a transfer uses it.
+    fn take_code(ctx: &Context) -> SendCode {
+        let mut options = Self::load(ctx);
+        let code = options.code().unwrap_or_default();
+        options.custom_code.clear();
+        options.store(ctx);
+        code
+    }
+}
+
+fn show_advanced_options(ui: &mut Ui) {
+    let mut options = AdvancedOptions::load(ui.ctx());
+    CollapsingHeader::new("Advanced").show(ui, |ui| {
+        ui.add_enabled_ui(options.custom_code.trim().is_empty(), |ui| {
+            ui.add(
+                Slider::new(&mut options.word_count, AdvancedOptions::WORD_COUNTS)
+                    .text("words in code"),
+            )
+            .on_hover_text("Shorter codes are quicker to type, longer codes are harder to guess");
+        });
+        ui.add(
+            TextEdit::singleline(&mut options.custom_code)
+                .hint_text("Custom code, e.g. 7-guitarist-revenge"),
+        );
+        if let Err(error) = options.code() {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    });
+    options.store(ui.ctx());
+}
+
+#[must_use]
+enum TextInputResponse {
+    Send,
+}
+
+fn show_text_input(ui: &mut Ui, text: &mut String) -> Option<TextInputResponse> {
+    ui.add(
+        TextEdit::multiline(text)
+            .hint_text("Text message")
+            .desired_rows(3),
+    );
+    ui.add_space(5.);
+
+    let input_empty = text.trim().is_empty();
+    ui.add_enabled_ui(!input_empty, |ui| {
+        let send_text_button = Button::new("Send Text").min_size(MIN_BUTTON_SIZE);
+        ui.add(send_text_button)
+            .clicked()
+            .then_some(TextInputResponse::Send)
+    })
+    .inner
+}
+
 fn dropped_file_paths(input: &InputState) -> Vec<PathBuf> {
     input
         .raw
@@ -164,47 +412,103 @@ fn dropped_file_paths(input: &InputState) -> Vec<PathBuf> {
         .collect()
 }
 
+#[must_use]
+enum TransferProgressResponse {
+    ContinueInBackground,
+}
+
 fn show_transfer_progress(
     ui: &mut Ui,
     controller: &mut SendingController,
     send_request: &SendRequest,
-) {
+    recipient: &Recipient,
+) -> Option<TransferProgressResponse> {
     if cancel_button(ui, CancelLabel::Cancel) {
         controller.cancel();
     }
 
     match controller.progress() {
-        SendingProgress::Packing => show_packing_progress(ui, send_request),
+        SendingProgress::Packing(progress) => show_packing_progress(ui, progress, send_request),
         SendingProgress::Connecting => show_transmit_code_progress(ui),
-        SendingProgress::Connected(code) => show_transmit_code(ui, code, send_request),
-        SendingProgress::PreparingToSend => page_with_content(
+        SendingProgress::Connected(code) => {
+            let connection = Settings::load(ui.ctx())
+                .connection_with_rendezvous_server(recipient.rendezvous_server.as_ref());
+            show_transmit_code(ui, code, &connection.rendezvous_url, send_request)
+        }
+        SendingProgress::Verifying(verifier) => {
+            if show_verification_page(ui, verifier, send_request) {
+                controller.confirm_verification();
+            }
+        }
+        SendingProgress::PreparingToSend(verifier) => page_with_content(
             ui,
             "Connected to Peer",
             format!("Preparing to send {}", SendRequestDisplay(send_request)),
             ICON_UPLOAD,
             |ui| {
+                widgets::verifier(ui, verifier);
+                ui.add_space(10.0);
                 ui.spinner();
             },
         ),
-        SendingProgress::Sending(transit_info, Progress { value: sent, total }) => {
-            page_with_content(
-                ui,
-                "Sending File",
-                format!(
-                    "{}{}",
-                    SendRequestDisplay(send_request),
-                    TransitInfoDisplay(transit_info)
-                ),
-                ICON_UPLOAD,
-                |ui| {
-                    ui.add(ProgressBar::new((*sent as f64 / *total as f64) as f32).animate(true));
-                },
-            )
-        }
+        SendingProgress::Sending(transit_info, progress, rate) => page_with_content(
+            ui,
+            "Sending File",
+            format!(
+                "{}{}",
+                SendRequestDisplay(send_request),
+                TransitInfoDisplay(transit_info)
+            ),
+            ICON_UPLOAD,
+            |ui| show_transfer_rate(ui, progress, rate),
+        ),
     }
+
+    ui.add_space(10.0);
+    ui.button("Continue in Background")
+        .on_hover_text("Frees this view for another transfer")
+        .clicked()
+        .then_some(TransferProgressResponse::ContinueInBackground)
 }
 
-fn show_packing_progress(ui: &mut Ui, send_request: &SendRequest) {
+/// Returns `true` once the user has confirmed that the receiver sees the same verifier.
+fn show_verification_page(ui: &mut Ui, verifier: &Verifier, send_request: &SendRequest) -> bool {
+    page_with_content(
+        ui,
+        "Verify Your Peer",
+        format!(
+            "Before sending {}, make sure that the receiver sees the same symbols.",
+            SendRequestDisplay(send_request)
+        ),
+        ICON_UPLOAD,
+        |ui| {
+            widgets::verifier(ui, verifier);
+            ui.add_space(10.0);
+            ui.add(PrimaryButton::new("Symbols Match").min_size(MIN_BUTTON_SIZE))
+                .clicked()
+        },
+    )
+}
+
+fn show_transfer_rate(ui: &mut Ui, progress: &Progress, rate: &TransferRate) {
+    ui.add(ProgressBar::new((progress.value as f64 / progress.total as f64) as f32).animate(true));
+    ui.add_space(5.0);
+    ui.label(RichText::new(TransferProgressDisplay(progress, rate).to_string()).weak());
+}
+
+fn show_packing_progress(ui: &mut Ui, progress: &PackingProgress, send_request: &SendRequest) {
+    let PackingProgress {
+        files: Progress {
+            value: files,
+            total: total_files,
+        },
+        bytes: Progress {
+            value: bytes,
+            total: total_bytes,
+        },
+        current_file,
+    } = progress;
+
     page_with_content(
         ui,
         "Send File",
@@ -214,7 +518,21 @@ fn show_packing_progress(ui: &mut Ui, send_request: &SendRequest) {
         ),
         ICON_UPLOAD,
         |ui| {
-            ui.spinner();
+            if *total_files == 0 {
+                ui.spinner();
+                return;
+            }
+            ui.add(
+                ProgressBar::new((*files as f64 / *total_files as f64) as f32)
+                    .text(format!(
+                        "{files} of {total_files} files ({} of {})",
+                        ByteDisplay(bytes.bytes()),
+                        ByteDisplay(total_bytes.bytes())
+                    ))
+                    .animate(true),
+            );
+            ui.add_space(5.0);
+            ui.label(RichText::new(current_file.display().to_string()).weak());
         },
     )
 }
@@ -231,7 +549,7 @@ fn show_transmit_code_progress(ui: &mut Ui) {
     )
 }
 
-fn show_transmit_code(ui: &mut Ui, code: &Code, send_request: &SendRequest) {
+fn show_transmit_code(ui: &mut Ui, code: &Code, rendezvous_url: &Url, send_request: &SendRequest) {
     page_with_content(
         ui,
         "Your Transmit Code",
@@ -252,20 +570,65 @@ fn show_transmit_code(ui: &mut Ui, code: &Code, send_request: &SendRequest) {
                 ui.output_mut(|output| output.copied_text = code.to_string());
             }
 
+            let link = share_link(code.clone(), rendezvous_url);
             if ui
                 .button(format!("{ICON_LINK} Copy Link"))
                 .on_hover_text("Click to copy")
                 .clicked()
             {
-                ui.output_mut(|output| {
-                    output.copied_text = SharableWormholeTransferUri::new(code.clone()).to_string();
-                });
+                ui.output_mut(|output| output.copied_text = link.clone());
             }
+
+            ui.add_space(10.);
+            widgets::qr_code(ui, &link);
+        },
+    );
+}
+
+/// Text messages are not files, so they're left out of the history.
+fn start_tracking(request: &SendRequest) -> Option<TransferTracker> {
+    let name = match request {
+        SendRequest::File(path) | SendRequest::Folder(path) => {
+            filename_or_self(path).display().to_string()
+        }
+        SendRequest::Selection(_) => "Selection".to_owned(),
+        SendRequest::Text(_) => return None,
+        SendRequest::Cached(original_request, _) => return start_tracking(original_request),
+    };
+    Some(TransferTracker::new(Direction::Sent, name))
+}
+
+pub(crate) fn record_transfer(
+    ctx: &Context,
+    tracker: TransferTracker,
+    controller: &mut SendingController,
+    result: &Result<(), (PortalError, SendRequest)>,
+) {
+    let outcome = result
+        .as_ref()
+        .err()
+        .map_or(Outcome::Completed, |(error, _)| Outcome::from(error));
+    let content_hash = controller.content_hash().cloned();
+    let details = match controller.progress() {
+        SendingProgress::Sending(transit_info, progress, rate) => TransferDetails {
+            transit_info: Some(transit_info.as_ref()),
+            size: Some(progress.total),
+            duration: rate.elapsed,
+            ..Default::default()
+        },
+        _ => TransferDetails::default(),
+    };
+    tracker.finish(
+        ctx,
+        outcome,
+        TransferDetails {
+            content_hash,
+            ..details
         },
     );
 }
 
-struct SendRequestDisplay<'a>(&'a SendRequest);
+pub(crate) struct SendRequestDisplay<'a>(pub(crate) &'a SendRequest);
 
 impl fmt::Display for SendRequestDisplay<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -275,6 +638,7 @@ impl fmt::Display for SendRequestDisplay<'_> {
                 write!(f, "folder \"{}\"", filename_or_self(path).display())
             }
             SendRequest::Selection(_) => write!(f, "selection"),
+            SendRequest::Text(_) => write!(f, "text message"),
             SendRequest::Cached(original_request, _) => {
                 write!(f, "{}", SendRequestDisplay(original_request))
             }
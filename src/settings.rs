@@ -0,0 +1,223 @@
+use crate::egui_ext::ContextExt;
+use crate::widgets::{PrimaryButton, MIN_BUTTON_SIZE};
+use egui::{Button, Context, Id, TextEdit, Ui};
+use poll_promise::Promise;
+use portal_wormhole::receive::{ReceiveOptions, ReceiveTarget};
+use portal_wormhole::send::SendOptions;
+use portal_wormhole::ConnectionSettings;
+use rfd::{AsyncFileDialog, FileHandle};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use url::Url;
+
+/// User preferences, persisted across restarts using egui's memory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    pub(crate) connection: ConnectionSettings,
+    /// Where received files are saved, the Downloads folder if not set.
+    pub(crate) download_directory: Option<PathBuf>,
+    /// Whether received folders and Zip archives are extracted.
+    pub(crate) extract_archives: bool,
+    /// Whether the verifier has to be confirmed before a transfer starts.
+    pub(crate) require_verification: bool,
+}
+
+impl Settings {
+    pub(crate) fn receive_options(&self) -> ReceiveOptions {
+        ReceiveOptions {
+            target: match &self.download_directory {
+                Some(directory) => ReceiveTarget::Directory(directory.clone()),
+                None => ReceiveTarget::Downloads,
+            },
+            extract_archives: self.extract_archives,
+            ..ReceiveOptions::default()
+        }
+    }
+
+    pub(crate) fn send_options(&self) -> SendOptions {
+        SendOptions {
+            require_verification: self.require_verification,
+            ..SendOptions::default()
+        }
+    }
+
+    /// Links can point to a different rendezvous server than the configured one.
+    pub(crate) fn connection_with_rendezvous_server(
+        &self,
+        rendezvous_server: Option<&Url>,
+    ) -> ConnectionSettings {
+        match rendezvous_server {
+            Some(rendezvous_url) => ConnectionSettings {
+                rendezvous_url: rendezvous_url.clone(),
+                ..self.connection.clone()
+            },
+            None => self.connection.clone(),
+        }
+    }
+
+    pub(crate) fn load(ctx: &Context) -> Self {
+        ctx.memory_mut(|m| m.data.get_persisted::<Settings>(settings_id()))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn store(self, ctx: &Context) {
+        ctx.memory_mut(|m| m.data.insert_persisted(settings_id(), self));
+    }
+}
+
+fn settings_id() -> Id {
+    Id::new("settings")
+}
+
+/// Edits a copy of the settings which is only stored once the user saves.
+pub(crate) struct SettingsWindow {
+    settings: Settings,
+    rendezvous_url: String,
+    relay_servers: String,
+    download_directory: String,
+    folder_dialog: Option<Promise<Option<FileHandle>>>,
+    error: Option<String>,
+}
+
+impl SettingsWindow {
+    pub(crate) fn new(settings: Settings) -> Self {
+        Self {
+            rendezvous_url: settings.connection.rendezvous_url.to_string(),
+            relay_servers: relay_servers_to_string(&settings.connection.relay_servers),
+            download_directory: settings
+                .download_directory
+                .as_ref()
+                .map(|directory| directory.display().to_string())
+                .unwrap_or_default(),
+            folder_dialog: None,
+            settings,
+            error: None,
+        }
+    }
+
+    /// Returns `true` when the window should be closed.
+    pub(crate) fn show(&mut self, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut close = false;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| close = self.ui(ui));
+        close || !open
+    }
+
+    fn ui(&mut self, ui: &mut Ui) -> bool {
+        self.poll_folder_dialog();
+
+        ui.heading("Downloads");
+        ui.label("Save received files to");
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut self.download_directory).hint_text("Downloads"));
+            if ui
+                .add_enabled(self.folder_dialog.is_none(), Button::new("Choose…"))
+                .clicked()
+            {
+                self.folder_dialog =
+                    Some(ui.ctx().spawn_async(AsyncFileDialog::new().pick_folder()));
+            }
+        });
+        ui.checkbox(
+            &mut self.settings.extract_archives,
+            "Extract received folders and Zip archives",
+        );
+        ui.add_space(5.0);
+
+        ui.heading("Connection");
+        ui.label("Rendezvous server");
+        ui.add(TextEdit::singleline(&mut self.rendezvous_url));
+        ui.label("App ID");
+        ui.add(TextEdit::singleline(&mut self.settings.connection.app_id));
+        ui.label("Relay servers (one per line)");
+        ui.add(TextEdit::multiline(&mut self.relay_servers).desired_rows(2));
+        ui.checkbox(
+            &mut self.settings.require_verification,
+            "Confirm the verification symbols before transferring",
+        );
+
+        if ui.button("Restore Defaults").clicked() {
+            let connection = ConnectionSettings::default();
+            self.rendezvous_url = connection.rendezvous_url.to_string();
+            self.relay_servers = relay_servers_to_string(&connection.relay_servers);
+            self.settings.connection = connection;
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            if ui
+                .add(PrimaryButton::new("Save").min_size(MIN_BUTTON_SIZE))
+                .clicked()
+            {
+                match self.parse() {
+                    Ok(settings) => {
+                        settings.store(ui.ctx());
+                        return true;
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            ui.button("Cancel").clicked()
+        })
+        .inner
+    }
+
+    fn parse(&self) -> Result<Settings, String> {
+        let rendezvous_url = Url::parse(self.rendezvous_url.trim())
+            .map_err(|error| format!("Invalid rendezvous server: {error}"))?;
+        let relay_servers = self
+            .relay_servers
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                Url::parse(line)
+                    .map_err(|error| format!("Invalid relay server \"{line}\": {error}"))
+            })
+            .collect::<Result<_, _>>()?;
+        let connection = ConnectionSettings {
+            rendezvous_url,
+            app_id: self.settings.connection.app_id.trim().to_owned(),
+            relay_servers,
+        };
+        connection.validate().map_err(|error| error.to_string())?;
+        let download_directory = match self.download_directory.trim() {
+            "" => None,
+            directory if PathBuf::from(directory).is_dir() => Some(PathBuf::from(directory)),
+            directory => return Err(format!("The folder \"{directory}\" does not exist")),
+        };
+        Ok(Settings {
+            connection,
+            download_directory,
+            extract_archives: self.settings.extract_archives,
+            require_verification: self.settings.require_verification,
+        })
+    }
+
+    fn poll_folder_dialog(&mut self) {
+        if let Some(promise) = self.folder_dialog.take() {
+            match promise.try_take() {
+                Ok(Some(folder)) => self.download_directory = folder.path().display().to_string(),
+                Ok(None) => {}
+                Err(promise) => self.folder_dialog = Some(promise),
+            }
+        }
+    }
+}
+
+fn relay_servers_to_string(relay_servers: &[Url]) -> String {
+    relay_servers
+        .iter()
+        .map(Url::as_str)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,331 @@
+//! Makes sure that only one Portal window is open.
+//! Instead of opening a second window, new invocations (e.g. from clicking a link)
+//! forward their arguments to the running instance.
+
+use crate::StartupAction;
+use egui::Context;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+/// The arguments of an invocation, sent from the new to the running instance.
+#[derive(Debug, Serialize, Deserialize)]
+struct ForwardedArgs {
+    working_directory: PathBuf,
+    args: Vec<String>,
+}
+
+pub enum Instance {
+    /// No other instance is running, this one receives the arguments of later invocations.
+    Primary(PrimaryInstance),
+    /// The arguments have been forwarded to the running instance.
+    Forwarded,
+}
+
+pub struct PrimaryInstance {
+    #[cfg(unix)]
+    listener: Option<(std::os::unix::net::UnixListener, unix::InstanceLock)>,
+}
+
+/// Actions forwarded by other instances.
+pub(crate) struct ForwardedActions {
+    receiver: mpsc::Receiver<ForwardedArgs>,
+    #[cfg(unix)]
+    lock: Option<unix::InstanceLock>,
+}
+
+impl ForwardedActions {
+    pub(crate) fn try_recv(&self) -> Option<StartupAction> {
+        let forwarded = self.receiver.try_recv().ok()?;
+        Some(StartupAction::from_args_in(
+            &forwarded.args,
+            &forwarded.working_directory,
+        ))
+    }
+
+    /// Stops accepting forwarded actions, so that the next invocation opens a new window.
+    pub(crate) fn close(&mut self) {
+        #[cfg(unix)]
+        drop(self.lock.take());
+    }
+}
+
+#[cfg(unix)]
+pub use unix::acquire;
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use log::warn;
+    use rustix::fs::{flock, FlockOperation};
+    use rustix::process::getuid;
+    use std::env;
+    use std::fs::{self, DirBuilder, File, OpenOptions};
+    use std::io::{self, Read, Write};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    /// The instance that holds the lock owns the socket and removes it when the lock is dropped.
+    pub(crate) struct InstanceLock {
+        socket_path: PathBuf,
+        _file: File,
+    }
+
+    impl Drop for InstanceLock {
+        fn drop(&mut self) {
+            _ = fs::remove_file(&self.socket_path);
+        }
+    }
+
+    /// Forwards the arguments to the running instance if there is one,
+    /// otherwise starts listening for later invocations.
+    pub fn acquire(args: &[String]) -> Instance {
+        match instance_directory() {
+            Ok(directory) => acquire_in(&directory, args),
+            Err(error) => {
+                warn!("Failed to prepare the single instance directory: {error}");
+                Instance::Primary(PrimaryInstance { listener: None })
+            }
+        }
+    }
+
+    /// The instance that holds the lock in `directory` is the primary instance.
+    pub(super) fn acquire_in(directory: &Path, args: &[String]) -> Instance {
+        let socket_path = directory.join("portal.sock");
+        match lock(&directory.join("portal.lock")) {
+            Ok(Some(file)) => {
+                // The socket of an instance that didn't exit cleanly is left behind.
+                _ = fs::remove_file(&socket_path);
+                let listener = UnixListener::bind(&socket_path)
+                    .inspect_err(|error| {
+                        warn!("Failed to listen on {}: {error}", socket_path.display())
+                    })
+                    .ok();
+                let lock = InstanceLock {
+                    socket_path,
+                    _file: file,
+                };
+                Instance::Primary(PrimaryInstance {
+                    listener: listener.map(|listener| (listener, lock)),
+                })
+            }
+            Ok(None) => match connect(&socket_path).and_then(|stream| forward(stream, args)) {
+                Ok(()) => Instance::Forwarded,
+                Err(error) => {
+                    warn!("Failed to forward arguments to running instance: {error}");
+                    Instance::Primary(PrimaryInstance { listener: None })
+                }
+            },
+            Err(error) => {
+                warn!("Failed to lock the single instance lock file: {error}");
+                Instance::Primary(PrimaryInstance { listener: None })
+            }
+        }
+    }
+
+    /// Returns the locked file or `None` if another instance holds the lock.
+    fn lock(path: &Path) -> io::Result<Option<File>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        match flock(&file, FlockOperation::NonBlockingLockExclusive) {
+            Ok(()) => Ok(Some(file)),
+            Err(rustix::io::Errno::WOULDBLOCK) => Ok(None),
+            Err(errno) => Err(errno.into()),
+        }
+    }
+
+    /// The instance that holds the lock may still be starting up.
+    fn connect(socket_path: &Path) -> io::Result<UnixStream> {
+        const ATTEMPTS: u32 = 20;
+        let mut attempt = 1;
+        loop {
+            match UnixStream::connect(socket_path) {
+                Ok(stream) => {
+                    check_peer(&stream)?;
+                    return Ok(stream);
+                }
+                Err(_) if attempt < ATTEMPTS => {
+                    attempt += 1;
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    pub(super) fn forward(mut stream: UnixStream, args: &[String]) -> io::Result<()> {
+        let forwarded = ForwardedArgs {
+            working_directory: env::current_dir()?,
+            args: args.to_vec(),
+        };
+        serde_json::to_writer(&mut stream, &forwarded)?;
+        stream.flush()
+    }
+
+    impl PrimaryInstance {
+        pub(crate) fn listen(self, ctx: Context) -> ForwardedActions {
+            let (sender, receiver) = mpsc::channel();
+            let lock = self.listener.map(|(listener, lock)| {
+                thread::spawn(move || accept(listener, sender, ctx));
+                lock
+            });
+            ForwardedActions { receiver, lock }
+        }
+    }
+
+    fn accept(listener: UnixListener, sender: mpsc::Sender<ForwardedArgs>, ctx: Context) {
+        for stream in listener.incoming() {
+            let forwarded = stream.and_then(|stream| {
+                check_peer(&stream)?;
+                read_forwarded_args(stream)
+            });
+            match forwarded {
+                Ok(forwarded) => {
+                    if sender.send(forwarded).is_err() {
+                        return;
+                    }
+                    ctx.request_repaint();
+                }
+                Err(error) => warn!("Failed to receive forwarded arguments: {error}"),
+            }
+        }
+    }
+
+    pub(super) fn read_forwarded_args(mut stream: UnixStream) -> io::Result<ForwardedArgs> {
+        let mut message = Vec::new();
+        stream.read_to_end(&mut message)?;
+        Ok(serde_json::from_slice(&message)?)
+    }
+
+    /// Forwarded arguments contain transfer codes, so they're only exchanged with our own user.
+    /// Where the peer can't be checked, the private instance directory keeps other users out.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn check_peer(stream: &UnixStream) -> io::Result<()> {
+        let credentials = rustix::net::sockopt::get_socket_peercred(stream)?;
+        if credentials.uid == getuid() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the peer belongs to a different user",
+            ))
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn check_peer(_stream: &UnixStream) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A directory that only the user can access: in the runtime directory if there is one,
+    /// otherwise in the temporary directory, which is shared with other users.
+    fn instance_directory() -> io::Result<PathBuf> {
+        let directory = match env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime_directory) => PathBuf::from(runtime_directory).join("portal"),
+            None => env::temp_dir().join(format!("portal-{}", getuid().as_raw())),
+        };
+        private_directory(&directory)?;
+        Ok(directory)
+    }
+
+    /// Creates the directory, or makes sure that an existing one hasn't been
+    /// created by someone else.
+    pub(super) fn private_directory(path: &Path) -> io::Result<()> {
+        match DirBuilder::new().mode(0o700).create(path) {
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+            result => result?,
+        }
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.is_dir()
+            && metadata.uid() == getuid().as_raw()
+            && metadata.permissions().mode() & 0o077 == 0
+        {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is not a private directory", path.display()),
+            ))
+        }
+    }
+}
+
+/// Other platforms always open a new window.
+#[cfg(not(unix))]
+pub fn acquire(_args: &[String]) -> Instance {
+    Instance::Primary(PrimaryInstance {})
+}
+
+#[cfg(not(unix))]
+impl PrimaryInstance {
+    pub(crate) fn listen(self, _ctx: Context) -> ForwardedActions {
+        let (_, receiver) = mpsc::channel();
+        ForwardedActions { receiver }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::unix::*;
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use tempfile::TempDir;
+
+    #[test]
+    fn forwards_args_with_working_directory() {
+        let (sender, receiver) = UnixStream::pair().expect("failed to create socket pair");
+        let args = vec!["wormhole-transfer:4-hurricane-equipment".to_owned()];
+        let forwarding = thread::spawn(move || forward(sender, &args));
+
+        let forwarded = read_forwarded_args(receiver).expect("failed to read arguments");
+        forwarding
+            .join()
+            .expect("forwarding panicked")
+            .expect("failed to forward arguments");
+        assert_eq!(forwarded.args, ["wormhole-transfer:4-hurricane-equipment"]);
+        assert!(forwarded.working_directory.is_absolute());
+    }
+
+    #[test]
+    fn forwards_args_to_instance_that_holds_lock() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let Instance::Primary(primary) = acquire_in(temp_dir.path(), &[]) else {
+            panic!("first instance to be primary");
+        };
+        let (listener, lock) = primary.listener.expect("primary to listen");
+
+        let args = vec!["file.txt".to_owned()];
+        assert!(matches!(
+            acquire_in(temp_dir.path(), &args),
+            Instance::Forwarded
+        ));
+        let (stream, _) = listener.accept().expect("forwarded connection");
+        let forwarded = read_forwarded_args(stream).expect("failed to read arguments");
+        assert_eq!(forwarded.args, args);
+
+        let socket_path = temp_dir.path().join("portal.sock");
+        assert!(socket_path.exists());
+        drop(lock);
+        assert!(!socket_path.exists());
+    }
+
+    #[test]
+    fn rejects_directory_that_others_can_access() {
+        let temp_dir = TempDir::new().expect("temp dir to be created");
+        let directory = temp_dir.path().join("portal");
+        private_directory(&directory).expect("directory to be created");
+
+        fs::set_permissions(&directory, fs::Permissions::from_mode(0o777))
+            .expect("permissions to be changed");
+        assert!(private_directory(&directory).is_err());
+    }
+}
@@ -1,22 +1,68 @@
 use portal_wormhole::{Code, WormholeTransferUri};
+use std::env;
 use std::error::Error;
+use std::path::{self, Path, PathBuf};
 use std::str::FromStr;
-use thiserror::Error;
+use url::Url;
 
 #[derive(Default, Debug)]
 pub enum StartupAction {
     #[default]
     None,
     ReceiveFile(ReceiveFileAction),
+    SendFiles(SendFilesAction),
+    SendToPeer(SendToPeerAction),
     ShowInvalidUriError(Box<dyn Error>),
 }
 
 #[derive(Debug)]
 pub struct ReceiveFileAction {
     pub code: Code,
+    /// The rendezvous server to use instead of the configured one.
+    pub rendezvous_server: Option<Url>,
 }
 
+#[derive(Debug)]
+pub struct SendFilesAction {
+    pub paths: Vec<PathBuf>,
+}
+
+/// The peer has created the code (i.e. is the leader) and waits for us to send something,
+/// e.g. because only our device has a camera to scan the peer's QR code.
+#[derive(Debug, Clone)]
+pub struct SendToPeerAction {
+    pub code: Code,
+    /// The rendezvous server to use instead of the configured one.
+    pub rendezvous_server: Option<Url>,
+}
+
+const WORMHOLE_TRANSFER_SCHEME: &str = "wormhole-transfer:";
+
 impl StartupAction {
+    /// Arguments are either a single `wormhole-transfer:` URI or paths to send,
+    /// e.g. when Portal is used to open files from a file manager.
+    pub fn from_args(args: &[String]) -> Self {
+        match env::current_dir() {
+            Ok(working_directory) => Self::from_args_in(args, &working_directory),
+            Err(_) => Self::from_args_in(args, Path::new("")),
+        }
+    }
+
+    /// Like [`StartupAction::from_args`], but resolves relative paths against
+    /// the working directory of another process, e.g. a forwarding instance.
+    pub fn from_args_in(args: &[String], working_directory: &Path) -> Self {
+        match args {
+            [uri] if is_wormhole_transfer_uri(uri) => Self::from_uri(Some(uri)),
+            [] => StartupAction::None,
+            paths => StartupAction::SendFiles(SendFilesAction {
+                paths: paths
+                    .iter()
+                    .map(|path| absolute_path(&working_directory.join(path)))
+                    .collect(),
+            }),
+        }
+    }
+
     pub fn from_uri(uri: Option<&str>) -> Self {
         uri.map(Self::from_uri_str).unwrap_or_default()
     }
@@ -27,15 +73,127 @@ impl StartupAction {
             .unwrap_or_else(|error| StartupAction::ShowInvalidUriError(error.into()))
     }
 
-    fn from_wormhole_transfer_uri(uri: WormholeTransferUri) -> Self {
-        if uri.is_leader || uri.rendezvous_server.is_some() {
-            StartupAction::ShowInvalidUriError(UnsupportedWormholeUriError(uri).into())
+    pub(crate) fn from_wormhole_transfer_uri(uri: WormholeTransferUri) -> Self {
+        if uri.is_leader {
+            StartupAction::SendToPeer(SendToPeerAction {
+                code: uri.code,
+                rendezvous_server: uri.rendezvous_server,
+            })
         } else {
-            StartupAction::ReceiveFile(ReceiveFileAction { code: uri.code })
+            StartupAction::ReceiveFile(ReceiveFileAction {
+                code: uri.code,
+                rendezvous_server: uri.rendezvous_server,
+            })
         }
     }
 }
 
-#[derive(Error, Debug)]
-#[error("Unsupported wormhole-transfer URI: {}", ToString::to_string(.0))]
-struct UnsupportedWormholeUriError(WormholeTransferUri);
+fn is_wormhole_transfer_uri(arg: &str) -> bool {
+    arg.get(..WORMHOLE_TRANSFER_SCHEME.len())
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case(WORMHOLE_TRANSFER_SCHEME))
+}
+
+/// Relative paths are resolved now, as the working directory may change later on.
+fn absolute_path(path: &Path) -> PathBuf {
+    path::absolute(path).unwrap_or_else(|_| path.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_args(args: &[&str]) -> StartupAction {
+        let args: Vec<_> = args.iter().map(ToString::to_string).collect();
+        StartupAction::from_args(&args)
+    }
+
+    #[test]
+    fn receives_from_follower_uri() {
+        let action = from_args(&["wormhole-transfer:4-hurricane-equipment"]);
+        let StartupAction::ReceiveFile(action) = action else {
+            panic!("expected receive action, got {action:?}");
+        };
+        assert_eq!(action.code.to_string(), "4-hurricane-equipment");
+        assert_eq!(action.rendezvous_server, None);
+    }
+
+    #[test]
+    fn receives_from_follower_uri_with_rendezvous_server() {
+        let action = from_args(&[
+            "wormhole-transfer:4-hurricane-equipment?rendezvous=ws%3A%2F%2Fexample.com%3A4000%2Fv1",
+        ]);
+        let StartupAction::ReceiveFile(action) = action else {
+            panic!("expected receive action, got {action:?}");
+        };
+        assert_eq!(action.code.to_string(), "4-hurricane-equipment");
+        assert_eq!(
+            action.rendezvous_server.map(String::from).as_deref(),
+            Some("ws://example.com:4000/v1")
+        );
+    }
+
+    #[test]
+    fn sends_to_leader_uri() {
+        let action = from_args(&["wormhole-transfer:8-peachy-dinosaur?role=leader"]);
+        let StartupAction::SendToPeer(action) = action else {
+            panic!("expected send to peer action, got {action:?}");
+        };
+        assert_eq!(action.code.to_string(), "8-peachy-dinosaur");
+        assert_eq!(action.rendezvous_server, None);
+    }
+
+    #[test]
+    fn sends_to_leader_uri_with_rendezvous_server() {
+        let action = from_args(&[
+            "wormhole-transfer:8-peachy-dinosaur?role=leader&rendezvous=ws%3A%2F%2Fexample.com%3A4000%2Fv1",
+        ]);
+        let StartupAction::SendToPeer(action) = action else {
+            panic!("expected send to peer action, got {action:?}");
+        };
+        assert_eq!(action.code.to_string(), "8-peachy-dinosaur");
+        assert_eq!(
+            action.rendezvous_server.map(String::from).as_deref(),
+            Some("ws://example.com:4000/v1")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_uris() {
+        for uri in [
+            "wormhole-transfer:8-peachy-dinosaur?role=spectator",
+            "wormhole-transfer:8-peachy-dinosaur?version=1",
+            "wormhole-transfer:",
+            "WORMHOLE-TRANSFER://host/8-peachy-dinosaur",
+        ] {
+            assert!(
+                matches!(from_args(&[uri]), StartupAction::ShowInvalidUriError(_)),
+                "{uri} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn sends_paths() {
+        let action = from_args(&["/tmp/a.txt", "b.txt"]);
+        let StartupAction::SendFiles(action) = action else {
+            panic!("expected send action, got {action:?}");
+        };
+        assert_eq!(action.paths[0], PathBuf::from("/tmp/a.txt"));
+        assert!(action.paths[1].is_absolute());
+    }
+
+    #[test]
+    fn resolves_paths_in_working_directory() {
+        let args = ["b.txt".to_owned()];
+        let action = StartupAction::from_args_in(&args, Path::new("/home/user"));
+        let StartupAction::SendFiles(action) = action else {
+            panic!("expected send action, got {action:?}");
+        };
+        assert_eq!(action.paths, [PathBuf::from("/home/user/b.txt")]);
+    }
+
+    #[test]
+    fn does_nothing_without_arguments() {
+        assert!(matches!(from_args(&[]), StartupAction::None));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::font::{ICON_CHECK, ICON_CLIPBOARD_COPY, ICON_DOWNLOAD, ICON_UPLOAD, ICON_X};
+use crate::history::TransferTracker;
+use crate::progress_display::TransferProgressDisplay;
+use crate::receive::{self, fraction};
+use crate::send::{self, SendRequestDisplay};
+use crate::widgets;
+use egui::{Align, Button, Context, Layout, ProgressBar, RichText, ScrollArea, Ui};
+use opener::reveal;
+use poll_promise::Promise;
+use portal_wormhole::receive::{ReceiveResult, ReceivingController};
+use portal_wormhole::send::{SendRequest, SendingController, SendingProgress};
+use portal_wormhole::PortalError;
+use std::path::PathBuf;
+
+type SendResult = Result<(), (PortalError, SendRequest)>;
+
+/// Transfers that continue in the background after the user
+/// has moved them out of the send or receive view to start another one.
+#[derive(Default)]
+pub(crate) struct Transfers {
+    transfers: Vec<Transfer>,
+}
+
+enum Transfer {
+    Sending {
+        promise: Promise<SendResult>,
+        controller: SendingController,
+        request: SendRequest,
+        tracker: Option<TransferTracker>,
+    },
+    Receiving {
+        promise: Promise<ReceiveResult>,
+        controller: ReceivingController,
+        filename: String,
+        tracker: TransferTracker,
+    },
+    Finished {
+        title: String,
+        result: Result<Option<PathBuf>, PortalError>,
+    },
+}
+
+impl Transfers {
+    pub(crate) fn push_send(
+        &mut self,
+        promise: Promise<SendResult>,
+        controller: SendingController,
+        request: SendRequest,
+        tracker: Option<TransferTracker>,
+    ) {
+        self.transfers.push(Transfer::Sending {
+            promise,
+            controller,
+            request,
+            tracker,
+        });
+    }
+
+    pub(crate) fn push_receive(
+        &mut self,
+        promise: Promise<ReceiveResult>,
+        controller: ReceivingController,
+        filename: String,
+        tracker: TransferTracker,
+    ) {
+        self.transfers.push(Transfer::Receiving {
+            promise,
+            controller,
+            filename,
+            tracker,
+        });
+    }
+
+    pub(crate) fn show(&mut self, ctx: &Context) {
+        self.poll(ctx);
+        if self.transfers.is_empty() {
+            return;
+        }
+
+        egui::TopBottomPanel::bottom("transfers")
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.add_space(5.0);
+                ui.strong("Background Transfers");
+                ScrollArea::vertical().max_height(220.0).show(ui, |ui| {
+                    self.transfers.retain_mut(|transfer| {
+                        ui.separator();
+                        !show_transfer(ui, transfer)
+                    });
+                });
+                ui.add_space(5.0);
+            });
+    }
+
+    fn poll(&mut self, ctx: &Context) {
+        for transfer in &mut self.transfers {
+            replace_with::replace_with(
+                transfer,
+                || Transfer::Finished {
+                    title: String::new(),
+                    result: Err(PortalError::Canceled),
+                },
+                |transfer| poll_transfer(ctx, transfer),
+            );
+        }
+    }
+}
+
+fn poll_transfer(ctx: &Context, transfer: Transfer) -> Transfer {
+    match transfer {
+        Transfer::Sending {
+            promise,
+            mut controller,
+            request,
+            tracker,
+        } => match promise.try_take() {
+            Ok(result) => {
+                if let Some(tracker) = tracker {
+                    send::record_transfer(ctx, tracker, &mut controller, &result);
+                }
+                Transfer::Finished {
+                    title: SendRequestDisplay(&request).to_string(),
+                    result: result.map(|_| None).map_err(|(error, _)| error),
+                }
+            }
+            Err(promise) => Transfer::Sending {
+                promise,
+                controller,
+                request,
+                tracker,
+            },
+        },
+        Transfer::Receiving {
+            promise,
+            mut controller,
+            filename,
+            tracker,
+        } => match promise.try_take() {
+            Ok(result) => {
+                receive::record_transfer(ctx, tracker, &mut controller, &result);
+                Transfer::Finished {
+                    title: format!("file \"{filename}\""),
+                    result: result.map(Some),
+                }
+            }
+            Err(promise) => Transfer::Receiving {
+                promise,
+                controller,
+                filename,
+                tracker,
+            },
+        },
+        finished @ Transfer::Finished { .. } => finished,
+    }
+}
+
+/// Returns `true` when the transfer has been dismissed.
+fn show_transfer(ui: &mut Ui, transfer: &mut Transfer) -> bool {
+    let (icon, title) = match transfer {
+        Transfer::Sending { request, .. } => (ICON_UPLOAD, SendRequestDisplay(request).to_string()),
+        Transfer::Receiving { filename, .. } => (ICON_DOWNLOAD, format!("file \"{filename}\"")),
+        Transfer::Finished {
+            title,
+            result: Ok(_),
+        } => (ICON_CHECK, title.clone()),
+        Transfer::Finished { title, .. } => (ICON_X, title.clone()),
+    };
+
+    let close_clicked = ui
+        .horizontal(|ui| {
+            ui.label(RichText::new(format!("{icon} {title}")).strong());
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                let label = match transfer {
+                    Transfer::Finished { .. } => "Dismiss",
+                    _ => "Cancel",
+                };
+                ui.add(Button::new(ICON_X.to_string()).frame(false))
+                    .on_hover_text(label)
+                    .clicked()
+            })
+            .inner
+        })
+        .inner;
+
+    match transfer {
+        Transfer::Sending { controller, .. } => {
+            show_sending_status(ui, controller);
+            if close_clicked {
+                controller.cancel();
+            }
+            false
+        }
+        Transfer::Receiving { controller, .. } => {
+            show_receiving_status(ui, controller);
+            if close_clicked {
+                controller.cancel();
+            }
+            false
+        }
+        Transfer::Finished { result, .. } => {
+            match result {
+                Ok(None) => _ = ui.label("Sent successfully"),
+                Ok(Some(path)) => {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Saved to {}", path.display()));
+                        if ui.small_button("Show in Folder").clicked() {
+                            _ = reveal(&path);
+                        }
+                    });
+                }
+                Err(PortalError::Canceled) => _ = ui.label("Canceled"),
+                Err(error) => _ = ui.colored_label(ui.visuals().error_fg_color, error.to_string()),
+            }
+            close_clicked
+        }
+    }
+}
+
+fn show_sending_status(ui: &mut Ui, controller: &mut SendingController) {
+    match controller.progress() {
+        SendingProgress::Packing(progress) => {
+            ui.label(format!(
+                "Packing {} of {} files...",
+                progress.files.value, progress.files.total
+            ));
+        }
+        SendingProgress::Connecting => _ = ui.label("Generating transmit code..."),
+        SendingProgress::Connected(code) => {
+            let code = code.to_string();
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(&code).strong());
+                if ui
+                    .small_button(ICON_CLIPBOARD_COPY.to_string())
+                    .on_hover_text("Copy Code")
+                    .clicked()
+                {
+                    ui.output_mut(|output| output.copied_text = code.clone());
+                }
+            });
+        }
+        SendingProgress::Verifying(verifier) => {
+            let verifier = verifier.clone();
+            widgets::verifier(ui, &verifier);
+            if ui.small_button("Symbols Match").clicked() {
+                controller.confirm_verification();
+            }
+        }
+        SendingProgress::PreparingToSend(_) => _ = ui.label("Connected to peer"),
+        SendingProgress::Sending(_, progress, rate) => {
+            ui.add(ProgressBar::new(fraction(progress.value, progress.total)).animate(true));
+            ui.label(RichText::new(TransferProgressDisplay(progress, rate).to_string()).weak());
+        }
+    }
+}
+
+fn show_receiving_status(ui: &mut Ui, controller: &mut ReceivingController) {
+    if let Some(progress) = controller.extraction_progress() {
+        let progress = *progress;
+        ui.add(ProgressBar::new(fraction(progress.value, progress.total)).text("Extracting"));
+        return;
+    }
+
+    let progress = *controller.progress();
+    let rate = *controller.rate();
+    if controller.transit_info().is_some() {
+        ui.add(ProgressBar::new(fraction(progress.value, progress.total)).animate(true));
+        ui.label(RichText::new(TransferProgressDisplay(&progress, &rate).to_string()).weak());
+    } else {
+        ui.label("Connected to peer");
+    }
+}
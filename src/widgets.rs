@@ -7,6 +7,14 @@ pub use self::primary_button::*;
 use egui::Vec2;
 mod toggle;
 pub use toggle::*;
+mod verifier;
+pub use verifier::*;
+mod qr_code;
+pub use qr_code::*;
+mod code_edit;
+pub use code_edit::*;
+mod content_hash;
+pub use content_hash::*;
 mod menu;
 pub(crate) use menu::*;
 
@@ -0,0 +1,77 @@
+use egui::text::{CCursor, CCursorRange, LayoutJob};
+use egui::{
+    Button, FontSelection, Key, Modifiers, Response, RichText, Stroke, TextEdit, TextFormat, Ui,
+};
+use portal_wormhole::{code_completions, complete_code, unknown_words};
+
+/// Completions are only listed once there are few enough to be useful.
+const MAX_LISTED_COMPLETIONS: usize = 5;
+
+/// A text field for entering a code, with tab completion of the words
+/// and unknown words highlighted.
+pub fn code_edit(ui: &mut Ui, code: &mut String) -> Response {
+    let mut layouter = |ui: &Ui, text: &str, wrap_width: f32| {
+        let mut job = highlight_unknown_words(ui, text);
+        job.wrap.max_width = wrap_width;
+        ui.fonts(|fonts| fonts.layout_job(job))
+    };
+    let output = TextEdit::singleline(code)
+        .hint_text("Code")
+        .lock_focus(true)
+        .layouter(&mut layouter)
+        .show(ui);
+    let response = output.response;
+
+    if response.has_focus() && ui.input_mut(|input| input.consume_key(Modifiers::NONE, Key::Tab)) {
+        if let Some(completed) = complete_code(code) {
+            set_code(ui, &response, code, completed);
+        }
+    }
+
+    let completions = code_completions(code);
+    if response.has_focus() && (2..=MAX_LISTED_COMPLETIONS).contains(&completions.len()) {
+        ui.horizontal_wrapped(|ui| {
+            for completion in completions {
+                let word = completion.rsplit('-').next().unwrap_or_default();
+                if ui
+                    .add(Button::new(RichText::new(word).weak()).small().frame(false))
+                    .clicked()
+                {
+                    set_code(ui, &response, code, completion);
+                }
+            }
+        });
+    }
+
+    response
+}
+
+/// Replaces the code and moves the cursor to the end, so that typing can continue.
+fn set_code(ui: &Ui, response: &Response, code: &mut String, completed: String) {
+    *code = completed;
+    if let Some(mut state) = TextEdit::load_state(ui.ctx(), response.id) {
+        let end = CCursor::new(code.chars().count());
+        state.cursor.set_char_range(Some(CCursorRange::one(end)));
+        state.store(ui.ctx(), response.id);
+    }
+    response.request_focus();
+}
+
+fn highlight_unknown_words(ui: &Ui, text: &str) -> LayoutJob {
+    let font_id = FontSelection::default().resolve(ui.style());
+    let format = TextFormat::simple(font_id.clone(), ui.visuals().text_color());
+    let unknown_format = TextFormat {
+        underline: Stroke::new(1.0, ui.visuals().error_fg_color),
+        ..TextFormat::simple(font_id, ui.visuals().error_fg_color)
+    };
+
+    let mut job = LayoutJob::default();
+    let mut position = 0;
+    for range in unknown_words(text) {
+        job.append(&text[position..range.start], 0.0, format.clone());
+        job.append(&text[range.clone()], 0.0, unknown_format.clone());
+        position = range.end;
+    }
+    job.append(&text[position..], 0.0, format);
+    job
+}
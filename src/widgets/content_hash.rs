@@ -0,0 +1,28 @@
+use crate::font::ICON_CHECK;
+use egui::{Label, RichText, Sense, Ui};
+use portal_wormhole::ContentHash;
+
+/// Shows whether both peers computed the same digest for the transferred file.
+/// The digest is shown on hover and copied on click, e.g. to compare it with a published one.
+pub fn content_hash(ui: &mut Ui, content_hash: &ContentHash) {
+    let (text, explanation) = if content_hash.verified {
+        (
+            format!("{ICON_CHECK} Verified"),
+            "Both sides computed the same SHA-256 digest",
+        )
+    } else {
+        (
+            "Not verified".to_owned(),
+            "The sender didn't tell us its SHA-256 digest to compare it with",
+        )
+    };
+    let response = ui
+        .add(Label::new(RichText::new(text).weak()).sense(Sense::click()))
+        .on_hover_text(format!(
+            "{explanation}:\n{}\n\nClick to copy",
+            content_hash.sha256
+        ));
+    if response.clicked() {
+        ui.output_mut(|output| output.copied_text = content_hash.sha256.clone());
+    }
+}
@@ -3,13 +3,33 @@ use crate::version::AppVersion;
 use egui::{menu, OpenUrl};
 use egui_theme_switch::global_theme_switch;
 
-pub(crate) fn app_menu(ctx: &egui::Context, latest_version: Option<AppVersion>) {
+#[must_use]
+pub(crate) enum AppMenuResponse {
+    OpenSettings,
+    OpenHistory,
+}
+
+pub(crate) fn app_menu(
+    ctx: &egui::Context,
+    latest_version: Option<AppVersion>,
+) -> Option<AppMenuResponse> {
+    let mut response = None;
+
     egui::TopBottomPanel::top("top panel").show(ctx, |ui| {
         menu::bar(ui, |ui| {
             let version = AppVersion::current();
 
             ui.menu_button("View", |ui| {
                 global_theme_switch(ui);
+                ui.separator();
+                if ui.button("History").clicked() {
+                    response = Some(AppMenuResponse::OpenHistory);
+                    ui.close_menu();
+                }
+                if ui.button("Settings").clicked() {
+                    response = Some(AppMenuResponse::OpenSettings);
+                    ui.close_menu();
+                }
             });
 
             ui.menu_button("Help", |ui| {
@@ -41,4 +61,6 @@ pub(crate) fn app_menu(ctx: &egui::Context, latest_version: Option<AppVersion>)
             }
         });
     });
+
+    response
 }
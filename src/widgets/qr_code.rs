@@ -0,0 +1,53 @@
+use egui::{Color32, ColorImage, Id, Image, TextureHandle, TextureOptions, Ui, Vec2};
+use qrcode::{Color, QrCode};
+
+const SMALL_SIZE: f32 = 100.0;
+const LARGE_SIZE: f32 = 260.0;
+
+/// Modules of white space around the code, as required by the QR code spec.
+const QUIET_ZONE: usize = 4;
+
+/// Shows the data as a QR code with a toggle to enlarge it,
+/// e.g. for scanning a link with a phone from across the desk.
+pub fn qr_code(ui: &mut Ui, data: &str) {
+    let id = Id::new("qr_code").with(data);
+    let Some(texture) = qr_code_texture(ui, id, data) else {
+        return;
+    };
+
+    let enlarged_id = Id::new("qr_code_enlarged");
+    let mut enlarged = ui.data(|d| d.get_temp(enlarged_id).unwrap_or(false));
+    let size = if enlarged { LARGE_SIZE } else { SMALL_SIZE };
+    ui.add(Image::new(&texture).fit_to_exact_size(Vec2::splat(size)));
+    let label = if enlarged { "Shrink" } else { "Enlarge" };
+    if ui.toggle_value(&mut enlarged, label).changed() {
+        ui.data_mut(|d| d.insert_temp(enlarged_id, enlarged));
+    }
+}
+
+/// The texture is kept in memory, so that the code is only encoded once.
+fn qr_code_texture(ui: &mut Ui, id: Id, data: &str) -> Option<TextureHandle> {
+    if let Some(texture) = ui.data(|d| d.get_temp::<TextureHandle>(id)) {
+        return Some(texture);
+    }
+    let image = qr_code_image(data)?;
+    let texture = ui
+        .ctx()
+        .load_texture(format!("qr_code_{data}"), image, TextureOptions::NEAREST);
+    ui.data_mut(|d| d.insert_temp(id, texture.clone()));
+    Some(texture)
+}
+
+fn qr_code_image(data: &str) -> Option<ColorImage> {
+    let code = QrCode::new(data).ok()?;
+    let width = code.width();
+    let size = width + 2 * QUIET_ZONE;
+    let mut image = ColorImage::new([size, size], Color32::WHITE);
+    for (index, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let (x, y) = (index % width + QUIET_ZONE, index / width + QUIET_ZONE);
+            image.pixels[y * size + x] = Color32::BLACK;
+        }
+    }
+    Some(image)
+}
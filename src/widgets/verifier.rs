@@ -0,0 +1,8 @@
+use egui::{RichText, Ui};
+use portal_wormhole::Verifier;
+
+/// Shows the verifier as emoji with their names below, so that they can also be read out loud.
+pub fn verifier(ui: &mut Ui, verifier: &Verifier) {
+    ui.label(RichText::new(verifier.emoji()).size(28.0));
+    ui.label(RichText::new(verifier.to_string()).weak());
+}